@@ -0,0 +1,18 @@
+use std::process::Command;
+
+// Passes the git revision the binary is built from on to the run manifest.
+fn main() {
+    let revision = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
+    if let Some(revision) = revision {
+        println!("cargo:rustc-env=FLUID_SENSE_REVISION={}", revision);
+    }
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
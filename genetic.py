@@ -1,5 +1,6 @@
 import copy
 import os
+import subprocess
 import random
 import numpy as np
 import matplotlib.pyplot as plt
@@ -18,18 +19,13 @@ def avaliar(ind):
     if os.path.exists(file_path):
         os.remove(file_path)
     
-    with open('config.yml', 'r') as f:
-        config = yaml.safe_load(f)    
-
-    if os.path.exists('config.yml'):
-        os.remove('config.yml')
-
-    with open('config.yml',"x") as tst:
-        config['simulation'] = ind
-        yaml.dump(config, tst, default_flow_style=True)
+    # Os parâmetros vão por --set, sem reescrever o config.yml
+    comando = ["cargo", "run", "--", "--config", "config.yml", "--headless"]
+    for chave, valor in ind.items():
+        comando += ["--set", f"simulation.{chave}={json.dumps(valor)}"]
 
     xyz = 0.0
-    os.system("cargo run -- --config config.yml --headless")
+    subprocess.run(comando)
     if os.path.exists(file_path):
         sm_df = pd.read_csv("saida.csv")
 
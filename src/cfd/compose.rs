@@ -0,0 +1,196 @@
+use crate::cfd::config::ConfigError;
+use crate::cfd::format;
+use crate::cfd::migrate;
+use crate::cfd::overrides::merge;
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// Reads a config and the files it includes, each upgraded to the current version. Included
+/// files are merged first, in order, and the including file is merged over them; include and
+/// mesh paths are relative to the file that names them.
+pub fn read_with_includes(filename: &str) -> Result<Value, ConfigError> {
+    read_included(Path::new(filename), &mut Vec::new())
+}
+
+fn read_included(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Value, ConfigError> {
+    let filename = path.to_string_lossy().to_string();
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| ConfigError::Io(filename.clone(), e))?;
+
+    if stack.contains(&canonical) {
+        return Err(ConfigError::Format(
+            filename,
+            "includes itself through a cycle".to_string(),
+        ));
+    }
+
+    let mut value = format::read_value(&filename)?;
+    migrate::upgrade(&mut value, &filename)?;
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    resolve_mesh(&mut value, directory);
+
+    let includes = match value.as_mapping_mut().and_then(|m| m.remove("include")) {
+        None => return Ok(value),
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Sequence(includes)) => includes
+            .into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(include),
+                _ => Err(ConfigError::Format(
+                    filename.clone(),
+                    "include entries must be file names".to_string(),
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => {
+            return Err(ConfigError::Format(
+                filename,
+                "include must be a file name or a list of file names".to_string(),
+            ))
+        }
+    };
+
+    let mut composed = Value::Mapping(Mapping::new());
+
+    stack.push(canonical);
+
+    for include in includes {
+        let included = read_included(&directory.join(include), stack)?;
+        merge(&mut composed, included);
+    }
+
+    stack.pop();
+    merge(&mut composed, value);
+
+    Ok(composed)
+}
+
+// A relative mesh path is read from the directory of the file that names it, like includes.
+fn resolve_mesh(value: &mut Value, directory: &Path) {
+    let mesh = value
+        .get_mut("environment")
+        .and_then(|environment| environment.get_mut("mesh"));
+
+    if let Some(Value::String(mesh)) = mesh {
+        if Path::new(mesh.as_str()).is_relative() {
+            *mesh = directory.join(mesh.as_str()).to_string_lossy().to_string();
+        }
+    }
+}
+
+/// Replaces `use: <template>` in actuator and sensor entries with the named entry of
+/// `templates`, with the entry's own fields taking precedence.
+pub fn apply_templates(value: &mut Value, filename: &str) -> Result<(), ConfigError> {
+    let templates = match value.as_mapping_mut().and_then(|m| m.remove("templates")) {
+        Some(Value::Mapping(templates)) => templates,
+        Some(_) => {
+            return Err(ConfigError::Format(
+                filename.to_string(),
+                "templates must be a mapping of names to device definitions".to_string(),
+            ))
+        }
+        None => Mapping::new(),
+    };
+
+    for section in ["actuators", "sensors"] {
+        let devices = match value.get_mut(section) {
+            Some(Value::Mapping(devices)) => devices,
+            _ => continue,
+        };
+
+        for (label, device) in devices.iter_mut() {
+            let label = format!("{}.{}", section, label.as_str().unwrap_or("?"));
+            *device = expand_template(device.clone(), &templates, &mut Vec::new()).map_err(
+                |message| {
+                    ConfigError::Format(filename.to_string(), format!("{}: {}", label, message))
+                },
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn expand_template(
+    mut device: Value,
+    templates: &Mapping,
+    stack: &mut Vec<String>,
+) -> Result<Value, String> {
+    let name = match device.as_mapping_mut().and_then(|m| m.remove("use")) {
+        None => return Ok(device),
+        Some(Value::String(name)) => name,
+        Some(_) => return Err("use must name a template".to_string()),
+    };
+
+    if stack.contains(&name) {
+        return Err(format!("template '{}' uses itself", name));
+    }
+
+    let template = templates
+        .get(name.as_str())
+        .cloned()
+        .ok_or_else(|| format!("unknown template '{}'", name))?;
+
+    stack.push(name);
+    let mut expanded = expand_template(template, templates, stack)?;
+    stack.pop();
+
+    merge(&mut expanded, device);
+
+    Ok(expanded)
+}
+
+/// Takes the `profiles` table out of the config, so it can be resolved against once the file
+/// is composed and again after overrides.
+pub fn take_profiles(value: &mut Value, filename: &str) -> Result<Mapping, ConfigError> {
+    match value.as_mapping_mut().and_then(|m| m.remove("profiles")) {
+        Some(Value::Mapping(profiles)) => Ok(profiles),
+        Some(_) => Err(ConfigError::Format(
+            filename.to_string(),
+            "profiles must be a mapping of names to simulation settings".to_string(),
+        )),
+        None => Ok(Mapping::new()),
+    }
+}
+
+/// Resolves `simulation: <profile>` or `simulation: {profile: <profile>, ...}` against the
+/// named entries of `profiles`.
+pub fn apply_profile(
+    value: &mut Value,
+    profiles: &Mapping,
+    filename: &str,
+) -> Result<(), ConfigError> {
+    let simulation = match value.get_mut("simulation") {
+        Some(simulation) => simulation,
+        None => return Ok(()),
+    };
+
+    let (name, settings) = match simulation {
+        Value::String(name) => (name.clone(), Value::Mapping(Mapping::new())),
+        Value::Mapping(settings) => match settings.remove("profile") {
+            Some(Value::String(name)) => (name, Value::Mapping(settings.clone())),
+            Some(_) => {
+                return Err(ConfigError::Format(
+                    filename.to_string(),
+                    "simulation.profile must name a profile".to_string(),
+                ))
+            }
+            None => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+
+    let mut profile = profiles.get(name.as_str()).cloned().ok_or_else(|| {
+        ConfigError::Format(
+            filename.to_string(),
+            format!("unknown simulation profile '{}'", name),
+        )
+    })?;
+
+    merge(&mut profile, settings);
+    *simulation = profile;
+
+    Ok(())
+}
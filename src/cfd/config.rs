@@ -1,80 +1,782 @@
+use crate::cfd::compose;
+use crate::cfd::migrate;
+use crate::cfd::overrides::{merge, Overrides};
+use crate::cfd::units;
 use glam::Vec3;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use strum_macros::EnumString;
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+/// Parameters of the SPH solver and the simulated fluid. Fields left out take their value
+/// from `preset`, or from `air_20C` when there is none.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Copy, Clone, PartialEq)]
+#[serde(default)]
 pub struct SimulationConfig {
+    /// Named set of parameters the other fields default to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<Preset>,
+    /// Smoothing radius of the SPH kernel, in meters.
+    #[schemars(range(min = 0.0))]
     pub radius: f32,
+    /// Mass of each particle, in kilograms.
+    #[schemars(range(min = 0.0))]
     pub mass: f32,
+    /// Stiffness of the equation of state relating density to pressure.
     pub gas_constant: f32,
+    /// Density the particles relax to. A solver constant, not the fluid's physical density.
+    #[schemars(range(min = 0.0))]
     pub rest_density: f32,
+    /// Rate at which neighbouring particles exchange heat.
+    #[schemars(range(min = 0.0))]
     pub thermal_conductivity: f32,
+    /// Guard added to divisors to avoid division by zero.
+    #[schemars(range(min = 0.0))]
     pub small_positive: f32,
+    /// Dynamic viscosity of the fluid.
+    #[schemars(range(min = 0.0))]
     pub viscosity: f32,
+    /// Strength of the velocity damping applied above the threshold.
+    #[schemars(range(min = 0.0))]
     pub damping_coefficient: f32,
+    /// Speed above which particles are damped, in meters per second.
+    #[schemars(range(min = 0.0))]
     pub damping_threshold: f32,
+    /// Time for a particle to lose half its excess heat, in seconds.
+    #[schemars(range(min = 0.0))]
     pub radiation_half_life: f32,
+    /// Strength of the thermal buoyancy force.
     pub buoyancy_coefficient: f32,
+    /// Direction warm particles rise in.
+    #[schemars(with = "[f32; 3]")]
     pub buoyancy_direction: Vec3,
+    /// Gravitational acceleration, in meters per second squared.
+    #[schemars(with = "[f32; 3]")]
     pub gravity: Vec3,
+    /// Position of the virtual particle used at boundaries.
+    #[schemars(with = "[f32; 3]")]
     pub virtual_particle: Vec3,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Preset::Air20C.config()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Clone, Copy)]
+pub enum Preset {
+    /// Air at room temperature: the solver parameters of config.yml, calibrated against the
+    /// room experiment.
+    #[serde(rename = "air_20C")]
+    Air20C,
+    /// Water, the parameters the solver was first tuned with.
+    #[serde(rename = "water")]
+    Water,
+}
+
+impl Preset {
+    /// Physical density of the fluid, in kilograms per liter, used to turn volume flows into
+    /// mass flows.
+    pub fn density(&self) -> f64 {
+        match self {
+            Preset::Air20C => 0.001204,
+            Preset::Water => 0.9982,
+        }
+    }
+
+    pub fn config(&self) -> SimulationConfig {
+        match self {
+            Preset::Air20C => SimulationConfig {
+                preset: None,
+                radius: 0.79146903,
+                mass: 0.05487241,
+                gas_constant: 5.850347,
+                rest_density: 1.7621887,
+                thermal_conductivity: 0.020976884,
+                small_positive: 0.6916739,
+                viscosity: 0.09693413,
+                damping_coefficient: 199.70644,
+                damping_threshold: 1.6944768,
+                radiation_half_life: 1999.816,
+                buoyancy_coefficient: 0.4203393,
+                buoyancy_direction: Vec3::Y,
+                gravity: Vec3::new(0.0, -9.82, 0.0),
+                virtual_particle: Vec3::new(0.0, 0.01828, 0.0),
+            },
+            Preset::Water => SimulationConfig {
+                preset: None,
+                radius: 0.0457,
+                mass: 0.02,
+                gas_constant: 3.0,
+                rest_density: 0.99829,
+                thermal_conductivity: 0.598,
+                small_positive: 0.0001,
+                viscosity: 0.01,
+                damping_coefficient: 100.0,
+                damping_threshold: 1.0,
+                radiation_half_life: 1000.0,
+                buoyancy_coefficient: 0.8,
+                buoyancy_direction: Vec3::Y,
+                gravity: Vec3::new(0.0, -9.82, 0.0),
+                virtual_particle: Vec3::new(0.0, 0.01828, 0.0),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
 pub struct ParticleConfig {
+    /// Rendered particle size, in meters.
+    #[schemars(range(min = 0.0))]
     pub size: f32,
+    /// Rendered color as RGB in 0..1.
+    #[schemars(with = "[f32; 3]")]
     pub color: Vec3,
 }
 
-#[derive(Serialize, Deserialize, Debug, EnumString, PartialEq, Clone, Copy)]
+/// Phase of the emitted fluid.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, EnumString, PartialEq, Clone, Copy)]
 pub enum FluidType {
     Gaseous,
     Liquid,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ActuatorConfig {
-    pub height: f32,
-    pub direction: Vec3,
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, Default)]
+#[serde(tag = "shape")]
+pub enum Nozzle {
+    #[default]
+    Point,
+    Disc {
+        radius: f32,
+    },
+    Rectangle {
+        width: f32,
+        height: f32,
+    },
+    Cone {
+        half_angle: f32,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct EmitterConfig {
+    /// Speed of emitted particles, in meters per second.
+    #[schemars(range(min = 0.0))]
     pub initial_velocity: f32,
+    /// Relative random variation of the emission speed.
+    #[serde(default)]
+    #[schemars(range(min = 0.0))]
+    pub velocity_spread: f32,
+    /// Temperature of emitted particles, in degrees Celsius.
     pub temperature: Option<f32>,
+    /// Size of the emission volume, in meters.
+    #[schemars(with = "[f32; 3]")]
     pub range: Vec3,
+    #[serde(default)]
+    pub nozzle: Nozzle,
     pub fluid_type: FluidType,
-    pub interval: f32,
+    /// Seconds between emitted particles.
+    #[schemars(range(min = 0.0))]
+    pub interval: Option<f32>,
+    /// Emitted mass per second, in kilograms; overrides `interval`.
+    #[schemars(range(min = 0.0))]
+    pub flow_rate: Option<f32>,
     pub particle: ParticleConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct FanConfig {
+    /// Radius of the air column the fan pushes, in meters.
+    #[schemars(range(min = 0.0))]
+    pub radius: f32,
+    /// Reach of the fan along its direction, in meters.
+    #[schemars(range(min = 0.0))]
+    pub length: f32,
+    /// Half angle of the fan's cone, in degrees.
+    #[serde(default)]
+    #[schemars(range(min = 0.0, max = 90.0))]
+    pub spread: f32,
+    /// Acceleration applied inside the column, in meters per second squared.
+    pub acceleration: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct HeaterConfig {
+    /// Radius of the heated volume, in meters.
+    #[schemars(range(min = 0.0))]
+    pub radius: f32,
+    /// Heating rate, in degrees Celsius per second; a negative rate cools.
+    pub power: f32,
+    /// Temperature particles are not heated beyond, in degrees Celsius. Hotter particles are
+    /// left as they are.
+    pub max_temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct ExtractorConfig {
+    /// Radius within which particles are removed, in meters.
+    #[schemars(range(min = 0.0))]
+    pub radius: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+#[serde(tag = "kind")]
+pub enum ActuatorKind {
+    Emitter(EmitterConfig),
+    Fan(FanConfig),
+    Heater(HeaterConfig),
+    Extractor(ExtractorConfig),
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct ScheduleSegment {
+    /// Start of the segment, in seconds.
+    #[schemars(range(min = 0.0))]
+    pub start: f32,
+    /// End of the segment, in seconds.
+    #[schemars(range(min = 0.0))]
+    pub end: f32,
+    /// Fraction of full output during the segment.
+    #[serde(default = "ScheduleSegment::default_intensity")]
+    #[schemars(range(min = 0.0))]
+    pub intensity: f32,
+    /// Intensity reached linearly by the end of the segment.
+    #[schemars(range(min = 0.0))]
+    pub ramp_to: Option<f32>,
+    pub temperature: Option<f32>,
+    pub initial_velocity: Option<f32>,
+    pub interval: Option<f32>,
+}
+
+impl ScheduleSegment {
+    fn default_intensity() -> f32 {
+        1.0
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(untagged)]
+pub enum ScheduleConfig {
+    Segments(Vec<ScheduleSegment>),
+    /// Keyframes read from a CSV with a `time` column. Times, intervals, temperatures and
+    /// velocities may carry units, as in `2 min`.
+    TimeSeries { csv: String },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, EnumString, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Facing {
+    North,
+    South,
+    East,
+    West,
+    Up,
+    Down,
+}
+
+impl Facing {
+    // The first line of the map is the northern edge of the room.
+    pub fn to_direction(self) -> Vec3 {
+        match self {
+            Facing::North => Vec3::NEG_Z,
+            Facing::South => Vec3::Z,
+            Facing::East => Vec3::X,
+            Facing::West => Vec3::NEG_X,
+            Facing::Up => Vec3::Y,
+            Facing::Down => Vec3::NEG_Y,
+        }
+    }
+}
+
+/// A device acting on the fluid, placed by its label on the map.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ActuatorConfig {
+    /// Mounting height above the floor, in meters.
+    pub height: f32,
+    /// Offset from the tile center, in meters.
+    #[serde(default)]
+    #[schemars(with = "[f32; 3]")]
+    pub offset: Vec3,
+    /// Absolute position in meters, used instead of the map glyph.
+    #[schemars(with = "Option<[f32; 3]>")]
+    pub position: Option<Vec3>,
+    /// Direction the device points in.
+    #[schemars(with = "Option<[f32; 3]>")]
+    pub direction: Option<Vec3>,
+    /// Compass direction the device points in, instead of `direction`.
+    pub facing: Option<Facing>,
+    /// Label of a device to point at.
+    pub aim_at: Option<String>,
+    #[serde(flatten)]
+    pub kind: ActuatorKind,
+    /// When and how strongly the device runs.
+    pub schedule: Option<ScheduleConfig>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, EnumString, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Quantity {
+    Temperature,
+    Velocity,
+    Speed,
+    Density,
+}
+
+/// A probe sampling the fluid at a single point, placed by its label on the map.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct SensorConfig {
+    /// Height above the floor, in meters.
     pub height: f32,
-    pub range: Vec3,
+    /// Offset from the tile center, in meters.
+    #[serde(default)]
+    #[schemars(with = "[f32; 3]")]
+    pub offset: Vec3,
+    /// Absolute position in meters, used instead of the map glyph.
+    #[schemars(with = "Option<[f32; 3]>")]
+    pub position: Option<Vec3>,
+    /// Size of the probe, in meters. Deprecated and ignored: readings are taken at a point.
+    #[deprecated(note = "sensors sample a single point; range is ignored")]
+    #[schemars(with = "Option<[f32; 3]>")]
+    pub range: Option<Vec3>,
+    /// File the readings are written to.
     pub output: Option<String>,
+    /// File format of the output.
+    #[serde(default)]
+    pub format: TableFormat,
+    /// Quantities to record.
+    #[serde(default = "SensorConfig::default_quantities")]
+    pub quantities: Vec<Quantity>,
+    /// Seconds between readings.
+    #[schemars(range(min = 0.0))]
+    pub interval: Option<f32>,
+}
+
+impl SensorConfig {
+    fn default_quantities() -> Vec<Quantity> {
+        vec![Quantity::Temperature]
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    /// Comma separated text, without metadata.
+    #[default]
+    Csv,
+    /// Apache Parquet, with the metadata as key-value pairs.
+    Parquet,
+    /// Arrow IPC file, with the metadata in the schema.
+    Arrow,
+    /// One JSON object per line, the first holding the metadata.
+    Jsonl,
+}
+
+impl TableFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Parquet => "parquet",
+            TableFormat::Arrow => "arrow",
+            TableFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct DevicePlacement {
+    /// Label of an actuator or sensor.
+    pub label: String,
+    /// Map column and row of the device.
+    pub tile: [usize; 2],
+    /// Offset from the tile center, in meters.
+    #[serde(default)]
+    #[schemars(with = "[f32; 3]")]
+    pub offset: Vec3,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TileKind {
+    Empty,
+    Wall,
+    Floor,
+    Obstacle,
+    User,
+    Door,
+    Vent,
+    Inlet,
+    Device,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LayerConfig {
+    /// Height of the layer above the floor, in meters.
+    #[schemars(range(min = 0.0))]
+    pub height: f32,
+    /// Map of the layer, drawn like the room map.
+    pub map: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy)]
+pub struct InletConfig {
+    /// Speed of incoming air, in meters per second.
+    #[schemars(range(min = 0.0))]
+    pub velocity: f32,
+    /// Temperature of incoming air, in degrees Celsius.
+    pub temperature: f32,
+    /// Incoming mass per second per inlet tile, in kilograms.
+    #[schemars(range(min = 0.0))]
+    pub flow_rate: f32,
+    pub fluid_type: FluidType,
+    pub particle: ParticleConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DoorConfig {
+    /// Whether doors are open when there is no schedule.
+    #[serde(default)]
+    pub open: bool,
+    /// When doors are open.
+    pub schedule: Option<ScheduleConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EnvironmentConfig {
+    /// Floor plan, one character per tile.
+    #[serde(default)]
+    pub map: String,
+    /// OBJ file with room geometry, relative to the config file. Its lowest and highest points
+    /// are the floor and ceiling of the room.
+    pub mesh: Option<String>,
+    /// Air entering through inlet tiles.
+    pub inlet: Option<InletConfig>,
+    /// When door tiles are open.
+    pub door: Option<DoorConfig>,
+    /// Size of a map tile, in meters.
+    #[serde(default = "EnvironmentConfig::default_cell_size")]
+    #[schemars(range(min = 0.0))]
+    pub cell_size: f32,
+    /// Obstacle maps above the floor.
+    #[serde(default)]
+    pub layers: Vec<LayerConfig>,
+}
+
+impl EnvironmentConfig {
+    fn default_cell_size() -> f32 {
+        1.0
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    /// Legacy `.vtk` polydata.
+    Vtk,
+    /// XML `.vtu` unstructured grid.
+    #[default]
+    Vtu,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ParticleOutputConfig {
+    /// Directory the frames and their `.pvd` collection are written to.
+    pub directory: String,
+    /// File format of the frames.
+    #[serde(default)]
+    pub format: SnapshotFormat,
+    /// Simulated seconds between frames.
+    #[schemars(range(min = 0.0))]
+    pub interval: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldFormat {
+    /// One NumPy array per quantity and frame, covering the whole grid.
+    Npy,
+    /// One table per height and frame, with a row per grid column.
+    Csv,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldQuantity {
+    Temperature,
+    Speed,
+    Concentration,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct FieldOutputConfig {
+    /// Directory the frames and their `fields.json` index are written to.
+    pub directory: String,
+    /// File format of the frames.
+    pub format: FieldFormat,
+    /// Spacing of the grid, in meters.
+    #[schemars(range(min = 0.0))]
+    pub resolution: f32,
+    /// Simulated seconds between frames.
+    #[schemars(range(min = 0.0))]
+    pub interval: f32,
+    /// Heights of the CSV slices, in meters.
+    #[serde(default)]
+    pub heights: Vec<f32>,
+    /// Quantities to interpolate.
+    #[serde(default = "FieldOutputConfig::default_quantities")]
+    pub quantities: Vec<FieldQuantity>,
+}
+
+impl FieldOutputConfig {
+    fn default_quantities() -> Vec<FieldQuantity> {
+        vec![
+            FieldQuantity::Temperature,
+            FieldQuantity::Speed,
+            FieldQuantity::Concentration,
+        ]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Default)]
+pub struct OutputConfig {
+    /// Periodic particle dumps for ParaView.
+    pub particles: Option<ParticleOutputConfig>,
+    /// Periodic fields interpolated on a regular grid.
+    pub fields: Option<FieldOutputConfig>,
+}
+
+/// A fluid-sense simulation: room, devices and solver parameters.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
-    environment: String,
-    actuators: HashMap<char, ActuatorConfig>,
-    sensors: HashMap<char, SensorConfig>,
+    /// Version of the config format; older versions are upgraded at load.
+    #[serde(default = "Config::current_version")]
+    version: u64,
+    /// Room geometry, as a map string or a mapping.
+    #[schemars(schema_with = "Config::string_or::<EnvironmentConfig>")]
+    environment: EnvironmentConfig,
+    /// Actuators by label.
+    actuators: HashMap<String, ActuatorConfig>,
+    /// Sensors by label.
+    sensors: HashMap<String, SensorConfig>,
+    /// Device placements besides the map glyphs.
+    #[serde(default)]
+    devices: Vec<DevicePlacement>,
+    /// Custom map glyphs.
+    #[serde(default)]
+    legend: HashMap<char, TileKind>,
+    /// Fluid and solver parameters, or the name of a profile.
+    #[serde(default)]
+    #[schemars(schema_with = "Config::string_or::<SimulationConfig>")]
     simulation: SimulationConfig,
+    /// Seed of the random numbers devices draw; a random one is picked when left out.
+    seed: Option<u64>,
+    /// Files written besides the sensor outputs.
+    #[serde(default)]
+    output: OutputConfig,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, serde_yaml::Error),
+    Format(String, String),
 }
 
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(filename, e) => write!(f, "could not open {}: {}", filename, e),
+            ConfigError::Parse(filename, e) => write!(f, "could not read {}: {}", filename, e),
+            ConfigError::Format(filename, message) => {
+                write!(f, "could not read {}: {}", filename, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
-    pub fn new(filename: &String) -> Self {
-        let file = std::fs::File::open(filename).expect("Could not open file");
-        serde_yaml::from_reader(file).expect("Could not read file")
+    fn current_version() -> u64 {
+        migrate::CURRENT_VERSION
+    }
+
+    // Both the environment and the simulation also accept a bare string, expanded at load.
+    fn string_or<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+        Schema::Object(SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    gen.subschema_for::<String>(),
+                    gen.subschema_for::<T>(),
+                ]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+
+    pub fn load(filename: &str, overrides: &Overrides) -> Result<Self, ConfigError> {
+        let mut value = compose::read_with_includes(filename)?;
+
+        Self::expand_environment(&mut value);
+        // Overrides refine the composed config, so templates and profiles are resolved first.
+        let profiles = compose::take_profiles(&mut value, filename)?;
+        compose::apply_templates(&mut value, filename)?;
+        compose::apply_profile(&mut value, &profiles, filename)?;
+        overrides.apply(&mut value)?;
+        migrate::upgrade(&mut value, filename)?;
+        // A patch may have replaced the environment with a bare map, or named another profile.
+        Self::expand_environment(&mut value);
+        compose::apply_profile(&mut value, &profiles, filename)?;
+        Self::apply_preset(&mut value, filename)?;
+        units::normalize(&mut value, filename)?;
+        Self::default_actuator_kind(&mut value);
+
+        let mut config: Config = serde_yaml::from_value(value)
+            .map_err(|e| ConfigError::Parse(filename.to_string(), e))?;
+        config.seed.get_or_insert_with(rand::random);
+
+        Ok(config)
+    }
+
+    // A bare string environment is the map alone, drawn at one meter per character.
+    fn expand_environment(value: &mut serde_yaml::Value) {
+        let environment = match value.get_mut("environment") {
+            Some(environment) if environment.is_string() => environment,
+            _ => return,
+        };
+
+        let mut mapping = serde_yaml::Mapping::new();
+        mapping.insert("map".into(), environment.clone());
+        *environment = serde_yaml::Value::Mapping(mapping);
+    }
+
+    // Fills the simulation fields the config leaves out from its preset, or from air_20C when
+    // it names none, so unit-tagged values are read against the density the run will use.
+    fn apply_preset(value: &mut serde_yaml::Value, filename: &str) -> Result<(), ConfigError> {
+        let root = match value {
+            serde_yaml::Value::Mapping(root) => root,
+            _ => return Ok(()),
+        };
+
+        let mut settings = match root.get("simulation") {
+            Some(serde_yaml::Value::Mapping(settings)) => settings.clone(),
+            Some(_) => return Ok(()),
+            None => serde_yaml::Mapping::new(),
+        };
+
+        let preset = settings
+            .remove("preset")
+            .map(|preset| {
+                serde_yaml::from_value::<Preset>(preset.clone()).map_err(|_| {
+                    ConfigError::Format(
+                        filename.to_string(),
+                        format!(
+                            "unknown simulation preset '{}', expected air_20C or water",
+                            serde_yaml::to_string(&preset).unwrap_or_default().trim()
+                        ),
+                    )
+                })
+            })
+            .transpose()?;
+
+        let mut resolved = serde_yaml::to_value(SimulationConfig {
+            preset,
+            ..preset.unwrap_or(Preset::Air20C).config()
+        })
+        .unwrap();
+        merge(&mut resolved, serde_yaml::Value::Mapping(settings));
+        root.insert("simulation".into(), resolved);
+
+        Ok(())
+    }
+
+    // Actuators written before `kind` existed are all particle emitters.
+    fn default_actuator_kind(value: &mut serde_yaml::Value) {
+        let actuators = match value.get_mut("actuators") {
+            Some(serde_yaml::Value::Mapping(actuators)) => actuators,
+            _ => return,
+        };
+
+        for (_, actuator) in actuators.iter_mut() {
+            if let serde_yaml::Value::Mapping(actuator) = actuator {
+                if !actuator.contains_key("kind") {
+                    actuator.insert("kind".into(), "Emitter".into());
+                }
+            }
+        }
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed.unwrap_or_default()
+    }
+
+    /// FNV-1a hash of the resolved config, identifying the parameters a result came from.
+    pub fn digest(&self) -> String {
+        let json = serde_json::to_value(self)
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+        let hash = json.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        format!("{:016x}", hash)
+    }
+
+    pub fn get_output(&self) -> &OutputConfig {
+        &self.output
     }
 
     pub fn get_environment(&self) -> &String {
-        &self.environment
+        &self.environment.map
+    }
+
+    pub fn get_cell_size(&self) -> f32 {
+        self.environment.cell_size
+    }
+
+    pub fn get_mesh(&self) -> Option<&String> {
+        self.environment.mesh.as_ref()
+    }
+
+    pub fn get_inlet(&self) -> Option<&InletConfig> {
+        self.environment.inlet.as_ref()
+    }
+
+    pub fn get_door(&self) -> Option<&DoorConfig> {
+        self.environment.door.as_ref()
+    }
+
+    pub fn get_layers(&self) -> &Vec<LayerConfig> {
+        &self.environment.layers
+    }
+
+    pub fn get_actuators(&self) -> &HashMap<String, ActuatorConfig> {
+        &self.actuators
+    }
+
+    pub fn get_sensors(&self) -> &HashMap<String, SensorConfig> {
+        &self.sensors
     }
 
-    pub fn get_actuator_by_label(&self, label: &char) -> Option<&ActuatorConfig> {
+    pub fn get_devices(&self) -> &Vec<DevicePlacement> {
+        &self.devices
+    }
+
+    pub fn get_legend(&self) -> &HashMap<char, TileKind> {
+        &self.legend
+    }
+
+    pub fn get_actuator_by_label(&self, label: &str) -> Option<&ActuatorConfig> {
         self.actuators.get(label)
     }
 
-    pub fn get_sensor_by_label(&self, label: &char) -> Option<&SensorConfig> {
+    pub fn get_sensor_by_label(&self, label: &str) -> Option<&SensorConfig> {
         self.sensors.get(label)
     }
 
@@ -82,3 +784,93 @@ impl Config {
         &self.simulation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_file;
+
+    const PROFILED: &str = "\
+environment: |
+  ###
+  #.#
+  ###
+actuators: {}
+sensors: {}
+profiles:
+  fast:
+    viscosity: 0.1
+    gas_constant: 9.0
+  slow:
+    viscosity: 0.5
+simulation: fast
+";
+
+    // A map and its devices, with no simulation settings.
+    const MINIMAL: &str = "\
+environment: |
+  ####
+  #aV#
+  ####
+actuators:
+  a:
+    height: 1.0
+    direction: [1.0, 0.0, 0.0]
+    fluid_type: Gaseous
+    temperature: 65.0
+    initial_velocity: 5.0
+    interval: 0.05
+    range: [0.2, 0.2, 0.2]
+    particle: {color: [0.0, 0.0, 1.0], size: 0.02}
+sensors:
+  V: {height: 1.0}
+";
+
+    fn load(name: &str, contents: &str, assignments: &[&str]) -> Config {
+        let filename = temp_file("config", name, contents);
+        let mut overrides = Overrides::new();
+
+        for assignment in assignments {
+            overrides.set(assignment).unwrap();
+        }
+
+        Config::load(&filename, &overrides).unwrap()
+    }
+
+    #[test]
+    fn air_preset_is_the_default() {
+        let config = load("minimal.yml", MINIMAL, &[]);
+
+        assert_eq!(*config.get_simulation_config(), Preset::Air20C.config());
+    }
+
+    #[test]
+    fn air_preset_matches_the_calibrated_config() {
+        let filename = concat!(env!("CARGO_MANIFEST_DIR"), "/config.yml");
+        let config = Config::load(filename, &Overrides::new()).unwrap();
+
+        assert_eq!(*config.get_simulation_config(), Preset::Air20C.config());
+    }
+
+    #[test]
+    fn overrides_refine_the_profile() {
+        let config = load("profile.yml", PROFILED, &["simulation.viscosity=0.3"]);
+        let simulation = config.get_simulation_config();
+
+        assert_eq!(simulation.viscosity, 0.3);
+        assert_eq!(simulation.gas_constant, 9.0);
+        assert_eq!(simulation.mass, Preset::Air20C.config().mass);
+    }
+
+    #[test]
+    fn overrides_can_name_another_profile() {
+        let config = load("switch.yml", PROFILED, &["simulation=slow"]);
+        let simulation = config.get_simulation_config();
+
+        assert_eq!(simulation.viscosity, 0.5);
+        assert_eq!(
+            simulation.gas_constant,
+            Preset::Air20C.config().gas_constant
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::cfd::config::ConfigError;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Yaml,
+    MapJson,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceConfig {
+    #[serde(default)]
+    actuators: Vec<Mapping>,
+    #[serde(default)]
+    sensors: Vec<Mapping>,
+    simulation: Option<Value>,
+}
+
+const SEPARATOR: &str = "---";
+
+pub fn detect(filename: &str, contents: &str) -> Layout {
+    match Path::new(filename).extension().and_then(|e| e.to_str()) {
+        Some("txt") | Some("map") => return Layout::MapJson,
+        Some("yml") | Some("yaml") => return Layout::Yaml,
+        _ => {}
+    }
+
+    match split_map(contents) {
+        Some((_, devices)) if devices.trim_start().starts_with('{') => Layout::MapJson,
+        _ => Layout::Yaml,
+    }
+}
+
+pub fn read_value(filename: &str) -> Result<Value, ConfigError> {
+    let contents =
+        std::fs::read_to_string(filename).map_err(|e| ConfigError::Io(filename.to_string(), e))?;
+
+    match detect(filename, &contents) {
+        Layout::Yaml => {
+            serde_yaml::from_str(&contents).map_err(|e| ConfigError::Parse(filename.to_string(), e))
+        }
+        Layout::MapJson => read_map_json(filename, &contents),
+    }
+}
+
+fn split_map(contents: &str) -> Option<(&str, &str)> {
+    let mut offset = 0;
+
+    for line in contents.split_inclusive('\n') {
+        if line.trim_end() == SEPARATOR {
+            return Some((&contents[..offset], &contents[offset + line.len()..]));
+        }
+
+        offset += line.len();
+    }
+
+    None
+}
+
+fn read_map_json(filename: &str, contents: &str) -> Result<Value, ConfigError> {
+    let format_error = |message: String| ConfigError::Format(filename.to_string(), message);
+
+    let (map, devices) = split_map(contents)
+        .ok_or_else(|| format_error(format!("missing '{}' after the map", SEPARATOR)))?;
+
+    let devices: DeviceConfig =
+        serde_json::from_str(devices).map_err(|e| format_error(e.to_string()))?;
+
+    let simulation = match devices.simulation {
+        Some(simulation) => simulation,
+        None => read_sibling_simulation(filename)?,
+    };
+
+    let mut config = Mapping::new();
+    config.insert("environment".into(), map.into());
+    config.insert("actuators".into(), by_label(filename, devices.actuators)?);
+    config.insert("sensors".into(), by_label(filename, devices.sensors)?);
+    config.insert("simulation".into(), simulation);
+
+    Ok(Value::Mapping(config))
+}
+
+fn by_label(filename: &str, devices: Vec<Mapping>) -> Result<Value, ConfigError> {
+    let mut labeled = Mapping::new();
+
+    for mut device in devices {
+        let label = device.remove("label").ok_or_else(|| {
+            ConfigError::Format(filename.to_string(), "device without a label".to_string())
+        })?;
+
+        labeled.insert(label, Value::Mapping(device));
+    }
+
+    Ok(Value::Mapping(labeled))
+}
+
+// Map files do not carry simulation parameters; they live in a config.json
+// next to the map or one directory up, as in assets/.
+fn read_sibling_simulation(filename: &str) -> Result<Value, ConfigError> {
+    let directory = Path::new(filename).parent().unwrap_or(Path::new(""));
+
+    let candidate = [
+        directory.join("config.json"),
+        directory.join("../config.json"),
+    ]
+    .into_iter()
+    .find(|path| path.exists())
+    .ok_or_else(|| {
+        ConfigError::Format(
+            filename.to_string(),
+            "no simulation parameters and no config.json next to the map".to_string(),
+        )
+    })?;
+
+    let candidate = candidate.to_string_lossy().to_string();
+    let contents =
+        std::fs::read_to_string(&candidate).map_err(|e| ConfigError::Io(candidate.clone(), e))?;
+
+    serde_yaml::from_str(&contents).map_err(|e| ConfigError::Parse(candidate, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfd::config::Config;
+    use crate::cfd::overrides::Overrides;
+    use crate::cfd::validate::{validate, Severity};
+    use crate::scene::world_map::{Tile, WorldMap};
+    use crate::testing::temp_file;
+
+    const ROOM: &str = r#"######
+#.@.!#
+#..V.#
+######
+---
+{
+  "actuators": [
+    {
+      "label": "!",
+      "height": 1.5,
+      "direction": [-1.0, 0.0, 0.0],
+      "initial_velocity": 5.0,
+      "range": [0.2, 0.2, 0.2],
+      "fluid_type": "Gaseous",
+      "interval": 0.05,
+      "particle": {"size": 0.2, "color": [0.0, 0.0, 1.0]}
+    }
+  ],
+  "sensors": [{"label": "V", "height": 1.0}],
+  "simulation": {"viscosity": 0.02}
+}
+"#;
+
+    #[test]
+    fn detects_maps_followed_by_json() {
+        assert_eq!(detect("room.map", ""), Layout::MapJson);
+        assert_eq!(detect("room", ROOM), Layout::MapJson);
+        assert_eq!(
+            detect("room", "environment: x\n---\nfoo: bar\n"),
+            Layout::Yaml
+        );
+    }
+
+    #[test]
+    fn classifies_the_tiles_of_a_map() {
+        let filename = temp_file("format", "room.txt", ROOM);
+        let config = Config::load(&filename, &Overrides::new()).unwrap();
+        let tiles = WorldMap::parse_tiles(&config, config.get_environment()).unwrap();
+
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(tiles[0][0], Tile::Wall);
+        assert_eq!(tiles[1][1], Tile::Floor);
+        assert_eq!(tiles[1][2], Tile::User);
+        assert_eq!(tiles[1][4], Tile::Device('!'));
+        assert_eq!(tiles[2][3], Tile::Device('V'));
+        assert!(config.get_actuator_by_label("!").is_some());
+        assert!(config.get_sensor_by_label("V").is_some());
+        assert_eq!(config.get_simulation_config().viscosity, 0.02);
+    }
+
+    #[test]
+    fn default_map_passes_validation() {
+        let filename = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/maps/default.txt");
+        let config = Config::load(filename, &Overrides::new()).unwrap();
+        let errors = validate(&config)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>();
+
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+}
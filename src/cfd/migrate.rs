@@ -0,0 +1,119 @@
+use crate::cfd::config::ConfigError;
+use serde_yaml::{Mapping, Value};
+
+/// Version of the config format this build reads and writes.
+pub const CURRENT_VERSION: u64 = 2;
+
+type Migration = fn(&mut Mapping);
+
+// Each step upgrades a config from the paired version to the next one.
+const MIGRATIONS: &[(u64, Migration)] = &[(1, environment_mapping)];
+
+/// Upgrades a config read from `filename` to the current version. Configs without a `version`
+/// predate versioning and are read as version 1.
+pub fn upgrade(value: &mut Value, filename: &str) -> Result<(), ConfigError> {
+    let format_error = |message: String| ConfigError::Format(filename.to_string(), message);
+
+    let config = match value.as_mapping_mut() {
+        Some(config) => config,
+        None => return Ok(()),
+    };
+
+    let mut version = match config.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|version| *version > 0)
+            .ok_or_else(|| format_error("version must be a positive whole number".to_string()))?,
+    };
+
+    if version > CURRENT_VERSION {
+        return Err(format_error(format!(
+            "version {} is newer than this build of fluid-sense reads ({})",
+            version, CURRENT_VERSION
+        )));
+    }
+
+    for (from, migration) in MIGRATIONS {
+        if version == *from {
+            migration(config);
+            version += 1;
+        }
+    }
+
+    // Keep the version at the top of migrated files.
+    config.remove("version");
+    let mut upgraded = Mapping::new();
+    upgraded.insert("version".into(), CURRENT_VERSION.into());
+    upgraded.extend(std::mem::take(config));
+    *config = upgraded;
+
+    Ok(())
+}
+
+// Version 1 drew the environment as a bare map string and carried an unused `simulation.step`.
+fn environment_mapping(config: &mut Mapping) {
+    if let Some(environment) = config.get_mut("environment") {
+        if environment.is_string() {
+            let mut mapping = Mapping::new();
+            mapping.insert("map".into(), environment.clone());
+            *environment = Value::Mapping(mapping);
+        }
+    }
+
+    if let Some(Value::Mapping(simulation)) = config.get_mut("simulation") {
+        simulation.remove("step");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgraded(text: &str) -> Result<Value, String> {
+        let mut value: Value = serde_yaml::from_str(text).unwrap();
+
+        upgrade(&mut value, "config.yml")
+            .map(|_| value)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn upgrades_version_1() {
+        let value =
+            upgraded("environment: \"###\\n#.#\\n###\"\nsimulation: {step: 0.5, viscosity: 0.1}\n")
+                .unwrap();
+        let keys = value
+            .as_mapping()
+            .unwrap()
+            .keys()
+            .map(|key| key.as_str().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys, ["version", "environment", "simulation"]);
+        assert_eq!(value["version"], Value::from(CURRENT_VERSION));
+        assert_eq!(value["environment"]["map"], Value::from("###\n#.#\n###"));
+        assert_eq!(
+            value["simulation"],
+            serde_yaml::from_str::<Value>("{viscosity: 0.1}").unwrap()
+        );
+    }
+
+    #[test]
+    fn leaves_current_configs_alone() {
+        let text = "version: 2\nenvironment: {map: \"#\"}\nsimulation: {step: 0.5}\n";
+
+        assert_eq!(
+            upgraded(text).unwrap(),
+            serde_yaml::from_str::<Value>(text).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        assert_eq!(
+            upgraded("version: 3").unwrap_err(),
+            "could not read config.yml: version 3 is newer than this build of fluid-sense reads (2)"
+        );
+    }
+}
@@ -1,2 +1,9 @@
+pub mod compose;
 pub mod config;
+pub mod format;
+pub mod migrate;
+pub mod overrides;
+pub mod schema;
 pub mod sph;
+pub mod units;
+pub mod validate;
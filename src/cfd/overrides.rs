@@ -0,0 +1,230 @@
+use crate::cfd::config::ConfigError;
+use crate::cfd::format;
+use serde_yaml::{Mapping, Value};
+
+/// Prefix of environment variables that override config values, with `__` between path
+/// segments: `FLUID_SENSE__simulation__viscosity=0.02`.
+const ENV_PREFIX: &str = "FLUID_SENSE__";
+
+#[derive(Debug)]
+struct Assignment {
+    source: String,
+    path: Vec<String>,
+    value: Value,
+}
+
+/// Changes applied on top of a loaded config, in order: `--set-file` patches, environment
+/// variables, then `--set` assignments.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    patches: Vec<Value>,
+    variables: Vec<Assignment>,
+    assignments: Vec<Assignment>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn patch_file(&mut self, filename: &str) -> Result<(), ConfigError> {
+        self.patches.push(format::read_value(filename)?);
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, assignment: &str) -> Result<(), ConfigError> {
+        let (path, value) = assignment.split_once('=').ok_or_else(|| {
+            ConfigError::Format(
+                "--set".to_string(),
+                format!("expected PATH=VALUE, got '{}'", assignment),
+            )
+        })?;
+
+        self.assignments.push(Assignment {
+            source: format!("--set {}", assignment),
+            path: path.split('.').map(str::to_string).collect(),
+            value: Self::parse_value(value),
+        });
+
+        Ok(())
+    }
+
+    pub fn read_env(&mut self) {
+        let mut variables = std::env::vars()
+            .filter_map(|(name, value)| {
+                let path = name.strip_prefix(ENV_PREFIX)?;
+
+                Some(Assignment {
+                    source: name.clone(),
+                    path: path.split("__").map(str::to_string).collect(),
+                    value: Self::parse_value(&value),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        variables.sort_by(|a, b| a.source.cmp(&b.source));
+        self.variables = variables;
+    }
+
+    pub fn apply(&self, config: &mut Value) -> Result<(), ConfigError> {
+        for patch in self.patches.iter() {
+            merge(config, patch.clone());
+        }
+
+        for assignment in self.variables.iter().chain(self.assignments.iter()) {
+            Self::assign(config, &assignment.path, assignment.value.clone())
+                .map_err(|message| ConfigError::Format(assignment.source.clone(), message))?;
+        }
+
+        Ok(())
+    }
+
+    // Values are read as YAML, so numbers, booleans and `[x, y, z]` keep their types.
+    fn parse_value(value: &str) -> Value {
+        serde_yaml::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+    }
+
+    fn assign(target: &mut Value, path: &[String], value: Value) -> Result<(), String> {
+        let (key, rest) = match path.split_first() {
+            Some(split) => split,
+            None => {
+                *target = value;
+                return Ok(());
+            }
+        };
+
+        let child = match target {
+            Value::Mapping(mapping) => {
+                if !mapping.contains_key(key.as_str()) {
+                    let empty = Value::Mapping(Mapping::new());
+                    mapping.insert(key.as_str().into(), empty);
+                }
+
+                mapping.get_mut(key.as_str()).unwrap()
+            }
+            Value::Sequence(sequence) => {
+                let index = key
+                    .parse::<usize>()
+                    .map_err(|_| format!("'{}' is not an index into a list", key))?;
+                let length = sequence.len();
+
+                sequence.get_mut(index).ok_or_else(|| {
+                    format!("index {} is out of range for a list of {}", index, length)
+                })?
+            }
+            Value::Null => {
+                *target = Value::Mapping(Mapping::new());
+                return Self::assign(target, path, value);
+            }
+            _ => return Err(format!("cannot set '{}' inside a plain value", key)),
+        };
+
+        Self::assign(child, rest, value)
+    }
+}
+
+/// Deep-merges `patch` into `target`: mappings are merged key by key, anything else is replaced.
+pub fn merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Mapping(target), Value::Mapping(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(config: &str, assignments: &[&str]) -> Result<Value, String> {
+        let mut value: Value = serde_yaml::from_str(config).unwrap();
+        let mut overrides = Overrides::new();
+
+        for assignment in assignments {
+            overrides.set(assignment).map_err(|e| e.to_string())?;
+        }
+
+        overrides
+            .apply(&mut value)
+            .map(|_| value)
+            .map_err(|e| e.to_string())
+    }
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn sets_dotted_paths() {
+        let value = apply(
+            "simulation: {viscosity: 0.1, mass: 0.05}",
+            &["simulation.viscosity=0.3", "output.fields.format=vtk"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            value,
+            yaml("simulation: {viscosity: 0.3, mass: 0.05}\noutput: {fields: {format: vtk}}")
+        );
+    }
+
+    #[test]
+    fn keeps_value_types() {
+        let value = apply("{}", &["a=2", "b=true", "c=[1.0, 0.0, 0.0]", "d=air_20C"]).unwrap();
+
+        assert_eq!(
+            value,
+            yaml("{a: 2, b: true, c: [1.0, 0.0, 0.0], d: air_20C}")
+        );
+    }
+
+    #[test]
+    fn indexes_lists() {
+        let value = apply(
+            "layers: [{height: 1.0}, {height: 2.0}]",
+            &["layers.1.height=2.5"],
+        )
+        .unwrap();
+
+        assert_eq!(value, yaml("layers: [{height: 1.0}, {height: 2.5}]"));
+    }
+
+    #[test]
+    fn rejects_bad_list_indices() {
+        let config = "layers: [{height: 1.0}]";
+
+        assert_eq!(
+            apply(config, &["layers.3.height=2.0"]).unwrap_err(),
+            "could not read --set layers.3.height=2.0: index 3 is out of range for a list of 1"
+        );
+        assert_eq!(
+            apply(config, &["layers.top.height=2.0"]).unwrap_err(),
+            "could not read --set layers.top.height=2.0: 'top' is not an index into a list"
+        );
+    }
+
+    #[test]
+    fn rejects_paths_through_plain_values() {
+        assert_eq!(
+            apply("simulation: fast", &["simulation.viscosity=0.3"]).unwrap_err(),
+            "could not read --set simulation.viscosity=0.3: cannot set 'viscosity' inside a plain value"
+        );
+    }
+
+    #[test]
+    fn rejects_assignments_without_a_value() {
+        assert_eq!(
+            apply("{}", &["simulation.viscosity"]).unwrap_err(),
+            "could not read --set: expected PATH=VALUE, got 'simulation.viscosity'"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::cfd::config::Config;
+use crate::cfd::units;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+
+// A number followed by a unit, as accepted by `units::normalize`.
+const QUANTITY_PATTERN: &str = r"^\s*[-+]?(\d+\.?\d*|\.\d+)([eE][-+]?\d+)?\s*\S+\s*$";
+
+/// JSON Schema of the config format, matching what `Config::load` accepts: unit-tagged
+/// strings for physical fields, actuators without a `kind`, which default to emitters, and the
+/// composition keys `include`, `templates`, `use`, `profiles` and `simulation.profile`.
+pub fn config_schema() -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Config)).unwrap();
+
+    accept_quantities(&mut schema, &units::quantity_fields());
+    default_emitter_kind(&mut schema);
+    describe_composition(&mut schema);
+
+    schema
+}
+
+fn accept_quantities(schema: &mut Value, fields: &HashSet<&str>) {
+    match schema {
+        Value::Object(object) => {
+            if let Some(Value::Object(properties)) = object.get_mut("properties") {
+                for (name, property) in properties.iter_mut() {
+                    if fields.contains(name.as_str()) {
+                        accept_quantity(property);
+                    }
+                }
+            }
+
+            object
+                .values_mut()
+                .for_each(|child| accept_quantities(child, fields));
+        }
+        Value::Array(array) => array
+            .iter_mut()
+            .for_each(|child| accept_quantities(child, fields)),
+        _ => {}
+    }
+}
+
+fn accept_quantity(property: &mut Value) {
+    let object = match property.as_object_mut() {
+        Some(object) => object,
+        None => return,
+    };
+
+    // Vectors take a unit per component.
+    if let Some(items) = object.get_mut("items") {
+        accept_quantity_value(items);
+    } else {
+        accept_quantity_value(property);
+    }
+}
+
+fn accept_quantity_value(schema: &mut Value) {
+    let mut number = match schema.take() {
+        Value::Object(object) => object,
+        other => {
+            *schema = other;
+            return;
+        }
+    };
+
+    let mut wrapper = Map::new();
+
+    for key in ["description", "default"] {
+        if let Some(value) = number.remove(key) {
+            wrapper.insert(key.to_string(), value);
+        }
+    }
+
+    wrapper.insert(
+        "anyOf".to_string(),
+        json!([number, {"type": "string", "pattern": QUANTITY_PATTERN}]),
+    );
+
+    *schema = Value::Object(wrapper);
+}
+
+fn default_emitter_kind(schema: &mut Value) {
+    let variants = match schema.pointer_mut("/definitions/ActuatorConfig/oneOf") {
+        Some(Value::Array(variants)) => variants,
+        _ => return,
+    };
+
+    for variant in variants.iter_mut() {
+        if variant.pointer("/properties/kind/enum") != Some(&json!(["Emitter"])) {
+            continue;
+        }
+
+        if let Some(Value::Array(required)) = variant.get_mut("required") {
+            required.retain(|field| field != "kind");
+        }
+    }
+}
+
+// Composition is resolved before the config is parsed, so these keys are not part of `Config`.
+// Entries that `use` a template and files that `include` others may leave out fields the
+// template or the included files provide, so both relax the usual required fields.
+fn describe_composition(schema: &mut Value) {
+    if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+        properties.insert(
+            "include".to_string(),
+            json!({
+                "description": "Files merged under this one, relative to it.",
+                "anyOf": [
+                    {"type": "string"},
+                    {"type": "array", "items": {"type": "string"}}
+                ]
+            }),
+        );
+        properties.insert(
+            "templates".to_string(),
+            json!({
+                "description": "Device definitions that actuators and sensors can `use`.",
+                "type": "object",
+                "additionalProperties": {"type": "object"}
+            }),
+        );
+        properties.insert(
+            "profiles".to_string(),
+            json!({
+                "description": "Named simulation settings, picked with `simulation.profile`.",
+                "type": "object",
+                "additionalProperties": {"$ref": "#/definitions/SimulationConfig"}
+            }),
+        );
+
+        for section in ["actuators", "sensors"] {
+            if let Some(entry) = properties
+                .get_mut(section)
+                .and_then(|devices| devices.get_mut("additionalProperties"))
+            {
+                let definition = entry.take();
+                *entry = json!({
+                    "anyOf": [
+                        definition,
+                        {
+                            "type": "object",
+                            "required": ["use"],
+                            "properties": {
+                                "use": {
+                                    "description": "Template the entry is based on; its own fields take precedence.",
+                                    "type": "string"
+                                }
+                            }
+                        }
+                    ]
+                });
+            }
+        }
+    }
+
+    if let Some(Value::Object(properties)) =
+        schema.pointer_mut("/definitions/SimulationConfig/properties")
+    {
+        properties.insert(
+            "profile".to_string(),
+            json!({
+                "description": "Profile these settings are merged over.",
+                "type": "string"
+            }),
+        );
+    }
+
+    if let Value::Object(root) = schema {
+        if let Some(required) = root.remove("required") {
+            root.insert(
+                "anyOf".to_string(),
+                json!([{"required": required}, {"required": ["include"]}]),
+            );
+        }
+    }
+}
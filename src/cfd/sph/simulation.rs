@@ -1,15 +1,18 @@
 use glam::Vec3;
+use serde::{Deserialize, Serialize};
 
 use crate::cfd::config::{Config, FluidType, SimulationConfig};
 use crate::cfd::sph::kernel::Kernel;
-use crate::{ParticleInstance, Tile, WorldMap};
+use crate::scene::world_map::Cell;
+use crate::{ParticleInstance, WorldMap};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimulationParticle {
     pub position: Vec3,
     velocity: Vec3,
     acceleration: Vec3,
     forces: Vec3,
+    external_acceleration: Vec3,
     density: f32,
     density_correction: f32,
     pub temperature: f32,
@@ -32,6 +35,7 @@ impl SimulationParticle {
             velocity,
             acceleration: Vec3::ZERO,
             forces: Vec3::ZERO,
+            external_acceleration: Vec3::ZERO,
             density: 0.0,
             density_correction: 0.0,
             temperature,
@@ -40,6 +44,29 @@ impl SimulationParticle {
             color,
         }
     }
+
+    pub fn apply_acceleration(&mut self, acceleration: Vec3) {
+        self.external_acceleration += acceleration;
+    }
+
+    pub fn get_velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    pub fn get_density(&self) -> f32 {
+        self.density
+    }
+
+    pub fn get_fluid_type(&self) -> FluidType {
+        self.fluid_type
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub temperature: f32,
+    pub velocity: Vec3,
+    pub density: f32,
 }
 
 pub struct SPH {
@@ -81,25 +108,92 @@ impl SPH {
         self.instances.remove(index);
     }
 
-    pub fn get_particles(&self) -> &Vec<SimulationParticle> {
-        &self.particles
-    }
-
-    pub fn check_particles(&mut self, world_map: &WorldMap) {
-        self.particles
+    pub fn remove_particles<F>(&mut self, predicate: F) -> usize
+    where
+        F: Fn(&SimulationParticle) -> bool,
+    {
+        let indices = self
+            .particles
             .iter()
             .enumerate()
-            .filter(
-                |(_, particle)| match world_map.get_tile_in_position(particle.position) {
-                    Tile::Floor => particle.position.y > 3.0 || particle.position.y < 0.0,
-                    _ => true,
-                },
-            )
+            .filter(|(_, particle)| predicate(particle))
             .map(|(idx, _)| idx)
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+
+        indices
             .iter()
             .rev()
             .for_each(|idx| self.remove_particle(*idx));
+
+        indices.len()
+    }
+
+    pub fn get_particles(&self) -> &Vec<SimulationParticle> {
+        &self.particles
+    }
+
+    pub fn get_particles_mut(&mut self) -> &mut Vec<SimulationParticle> {
+        &mut self.particles
+    }
+
+    /// Replaces every particle, as when resuming from a checkpoint.
+    pub fn set_particles(&mut self, particles: Vec<SimulationParticle>) {
+        self.particles.clear();
+        self.instances.clear();
+        particles
+            .into_iter()
+            .for_each(|particle| self.add_particle(particle));
+    }
+
+    /// Removes particles that left the room and returns where each of them ended up.
+    pub fn check_particles(&mut self, world_map: &WorldMap) -> Vec<Cell> {
+        let removed = self
+            .particles
+            .iter()
+            .map(|particle| world_map.cell_at(particle.position))
+            .filter(|cell| *cell != Cell::Air)
+            .collect();
+
+        self.remove_particles(|particle| world_map.is_solid(particle.position));
+
+        removed
+    }
+
+    pub fn interpolate(&self, position: Vec3) -> Option<Sample> {
+        let mut weight = 0.0f32;
+        let mut temperature = 0.0f32;
+        let mut velocity = Vec3::ZERO;
+        let mut density = 0.0f32;
+
+        for particle in self.particles.iter() {
+            let diff = position - particle.position;
+
+            if diff.length() > self.config.radius {
+                continue;
+            }
+
+            let w = self.kernel.w(diff);
+            density += self.config.mass * w;
+
+            if particle.density <= 0.0 {
+                continue;
+            }
+
+            let volume = self.config.mass / particle.density * w;
+            weight += volume;
+            temperature += volume * particle.temperature;
+            velocity += volume * particle.velocity;
+        }
+
+        if weight <= 0.0 {
+            return None;
+        }
+
+        Some(Sample {
+            temperature: temperature / weight,
+            velocity: velocity / weight,
+            density,
+        })
     }
 
     pub fn get_particle_instances(&self) -> &Vec<ParticleInstance> {
@@ -246,10 +340,13 @@ impl SPH {
 
                     pi.forces = (pressure + 1.0 * atmospheric_pressure)
                         + viscosity
-                        + pi.density * (self.config.gravity + buoyancy + damping);
+                        + pi.density
+                            * (self.config.gravity + buoyancy + damping + pi.external_acceleration);
                 }
                 FluidType::Liquid => {
-                    pi.forces = pressure + viscosity + pi.density * self.config.gravity;
+                    pi.forces = pressure
+                        + viscosity
+                        + pi.density * (self.config.gravity + pi.external_acceleration);
                 }
             }
         }
@@ -265,6 +362,7 @@ impl SPH {
                 (prev_acceleration + particle.acceleration) / 2.0 * time_step * time_step;
             particle.position +=
                 prev_velocity * time_step + prev_acceleration / 2.0 * time_step * time_step;
+            particle.external_acceleration = Vec3::ZERO;
 
             self.instances[i].position = particle.position;
         }
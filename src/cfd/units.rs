@@ -0,0 +1,422 @@
+use crate::cfd::config::{ConfigError, Preset};
+use serde_yaml::Value;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Physical dimension of a config field. Values are stored in the units the simulation uses:
+/// degrees Celsius, seconds, meters, kilograms and degrees of arc.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dimension {
+    Temperature,
+    TemperatureRate,
+    Time,
+    Length,
+    Velocity,
+    Acceleration,
+    Mass,
+    MassFlow,
+    VolumeFlow,
+    Angle,
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Dimension::Temperature => "a temperature",
+            Dimension::TemperatureRate => "a heating rate",
+            Dimension::Time => "a time",
+            Dimension::Length => "a length",
+            Dimension::Velocity => "a velocity",
+            Dimension::Acceleration => "an acceleration",
+            Dimension::Mass => "a mass",
+            Dimension::MassFlow => "a mass flow",
+            Dimension::VolumeFlow => "a volume flow",
+            Dimension::Angle => "an angle",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// (unit, dimension, scale, offset): value_in_base = value * scale + offset
+const UNITS: &[(&str, Dimension, f64, f64)] = &[
+    ("degC", Dimension::Temperature, 1.0, 0.0),
+    ("°C", Dimension::Temperature, 1.0, 0.0),
+    ("C", Dimension::Temperature, 1.0, 0.0),
+    ("K", Dimension::Temperature, 1.0, -273.15),
+    ("degF", Dimension::Temperature, 5.0 / 9.0, -32.0 * 5.0 / 9.0),
+    ("°F", Dimension::Temperature, 5.0 / 9.0, -32.0 * 5.0 / 9.0),
+    ("degC/s", Dimension::TemperatureRate, 1.0, 0.0),
+    ("K/s", Dimension::TemperatureRate, 1.0, 0.0),
+    ("degC/min", Dimension::TemperatureRate, 1.0 / 60.0, 0.0),
+    ("K/min", Dimension::TemperatureRate, 1.0 / 60.0, 0.0),
+    ("s", Dimension::Time, 1.0, 0.0),
+    ("ms", Dimension::Time, 0.001, 0.0),
+    ("min", Dimension::Time, 60.0, 0.0),
+    ("h", Dimension::Time, 3600.0, 0.0),
+    ("m", Dimension::Length, 1.0, 0.0),
+    ("cm", Dimension::Length, 0.01, 0.0),
+    ("mm", Dimension::Length, 0.001, 0.0),
+    ("ft", Dimension::Length, 0.3048, 0.0),
+    ("in", Dimension::Length, 0.0254, 0.0),
+    ("m/s", Dimension::Velocity, 1.0, 0.0),
+    ("cm/s", Dimension::Velocity, 0.01, 0.0),
+    ("km/h", Dimension::Velocity, 1.0 / 3.6, 0.0),
+    ("ft/min", Dimension::Velocity, 0.00508, 0.0),
+    ("m/s^2", Dimension::Acceleration, 1.0, 0.0),
+    ("m/s²", Dimension::Acceleration, 1.0, 0.0),
+    ("kg", Dimension::Mass, 1.0, 0.0),
+    ("g", Dimension::Mass, 0.001, 0.0),
+    ("mg", Dimension::Mass, 0.000001, 0.0),
+    ("kg/s", Dimension::MassFlow, 1.0, 0.0),
+    ("g/s", Dimension::MassFlow, 0.001, 0.0),
+    ("kg/min", Dimension::MassFlow, 1.0 / 60.0, 0.0),
+    ("kg/h", Dimension::MassFlow, 1.0 / 3600.0, 0.0),
+    ("L/s", Dimension::VolumeFlow, 1.0, 0.0),
+    ("L/min", Dimension::VolumeFlow, 1.0 / 60.0, 0.0),
+    ("L/h", Dimension::VolumeFlow, 1.0 / 3600.0, 0.0),
+    ("mL/s", Dimension::VolumeFlow, 0.001, 0.0),
+    ("m3/s", Dimension::VolumeFlow, 1000.0, 0.0),
+    ("m3/h", Dimension::VolumeFlow, 1000.0 / 3600.0, 0.0),
+    ("deg", Dimension::Angle, 1.0, 0.0),
+    ("°", Dimension::Angle, 1.0, 0.0),
+    ("rad", Dimension::Angle, 180.0 / std::f64::consts::PI, 0.0),
+];
+
+// Fields that accept unit-tagged values; `*` matches any key or list index.
+const FIELDS: &[(&str, Dimension)] = &[
+    ("simulation.radius", Dimension::Length),
+    ("simulation.mass", Dimension::Mass),
+    ("simulation.damping_threshold", Dimension::Velocity),
+    ("simulation.radiation_half_life", Dimension::Time),
+    ("simulation.gravity.*", Dimension::Acceleration),
+    ("environment.cell_size", Dimension::Length),
+    ("environment.layers.*.height", Dimension::Length),
+    ("environment.inlet.velocity", Dimension::Velocity),
+    ("environment.inlet.temperature", Dimension::Temperature),
+    ("environment.inlet.flow_rate", Dimension::MassFlow),
+    ("environment.inlet.particle.size", Dimension::Length),
+    ("environment.door.schedule.*.start", Dimension::Time),
+    ("environment.door.schedule.*.end", Dimension::Time),
+    ("devices.*.offset.*", Dimension::Length),
+    ("actuators.*.height", Dimension::Length),
+    ("actuators.*.offset.*", Dimension::Length),
+    ("actuators.*.position.*", Dimension::Length),
+    ("actuators.*.initial_velocity", Dimension::Velocity),
+    ("actuators.*.temperature", Dimension::Temperature),
+    ("actuators.*.range.*", Dimension::Length),
+    ("actuators.*.interval", Dimension::Time),
+    ("actuators.*.flow_rate", Dimension::MassFlow),
+    ("actuators.*.particle.size", Dimension::Length),
+    ("actuators.*.nozzle.radius", Dimension::Length),
+    ("actuators.*.nozzle.width", Dimension::Length),
+    ("actuators.*.nozzle.height", Dimension::Length),
+    ("actuators.*.nozzle.half_angle", Dimension::Angle),
+    ("actuators.*.radius", Dimension::Length),
+    ("actuators.*.length", Dimension::Length),
+    ("actuators.*.spread", Dimension::Angle),
+    ("actuators.*.acceleration", Dimension::Acceleration),
+    ("actuators.*.power", Dimension::TemperatureRate),
+    ("actuators.*.max_temperature", Dimension::Temperature),
+    ("actuators.*.schedule.*.start", Dimension::Time),
+    ("actuators.*.schedule.*.end", Dimension::Time),
+    ("actuators.*.schedule.*.temperature", Dimension::Temperature),
+    (
+        "actuators.*.schedule.*.initial_velocity",
+        Dimension::Velocity,
+    ),
+    ("actuators.*.schedule.*.interval", Dimension::Time),
+    ("sensors.*.height", Dimension::Length),
+    ("sensors.*.offset.*", Dimension::Length),
+    ("sensors.*.position.*", Dimension::Length),
+    ("sensors.*.range.*", Dimension::Length),
+    ("sensors.*.interval", Dimension::Time),
+    ("output.particles.interval", Dimension::Time),
+    ("output.fields.interval", Dimension::Time),
+    ("output.fields.resolution", Dimension::Length),
+    ("output.fields.heights.*", Dimension::Length),
+];
+
+// Columns of schedule CSVs that accept unit-tagged values, like the segment fields.
+const SCHEDULE_COLUMNS: &[(&str, Dimension)] = &[
+    ("time", Dimension::Time),
+    ("interval", Dimension::Time),
+    ("temperature", Dimension::Temperature),
+    ("initial_velocity", Dimension::Velocity),
+];
+
+/// Names of the fields that accept unit-tagged values.
+pub fn quantity_fields() -> HashSet<&'static str> {
+    FIELDS
+        .iter()
+        .filter_map(|(pattern, _)| pattern.split('.').rev().find(|segment| *segment != "*"))
+        .collect()
+}
+
+/// Replaces unit-tagged strings such as `"65 degC"` or `"2 L/min"` with plain numbers in the
+/// simulation's units. Volume flows become mass flows through the physical density of the
+/// preset's fluid, air at 20 °C when no preset is named.
+pub fn normalize(value: &mut Value, filename: &str) -> Result<(), ConfigError> {
+    let simulation = value.get("simulation");
+
+    if let Some(Value::String(density)) = simulation.and_then(|s| s.get("rest_density")) {
+        return Err(ConfigError::Format(
+            filename.to_string(),
+            format!(
+                "simulation.rest_density: '{}' is a solver constant and takes no unit",
+                density
+            ),
+        ));
+    }
+
+    let density = simulation
+        .and_then(|simulation| simulation.get("preset"))
+        .and_then(|preset| serde_yaml::from_value::<Preset>(preset.clone()).ok())
+        .unwrap_or(Preset::Air20C)
+        .density();
+
+    walk(value, &mut Vec::new(), density)
+        .map_err(|message| ConfigError::Format(filename.to_string(), message))
+}
+
+/// Reads a schedule CSV cell written as text: a plain number, or for the columns that take
+/// units, a number with a unit such as `"2 min"`.
+pub fn convert_schedule_cell(column: &str, text: &str) -> Result<f64, String> {
+    if let Ok(number) = text.trim().parse::<f64>() {
+        return Ok(number);
+    }
+
+    let (_, dimension) = SCHEDULE_COLUMNS
+        .iter()
+        .find(|(name, _)| *name == column)
+        .ok_or_else(|| format!("'{}' is not a number", text))?;
+
+    // Schedules carry no flows, so the density is never read.
+    convert(text, *dimension, 1.0)
+}
+
+fn walk(value: &mut Value, path: &mut Vec<String>, density: f64) -> Result<(), String> {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, child) in mapping.iter_mut() {
+                path.push(key.as_str().map_or_else(|| "?".to_string(), str::to_string));
+                walk(child, path, density)?;
+                path.pop();
+            }
+        }
+        Value::Sequence(sequence) => {
+            for (i, child) in sequence.iter_mut().enumerate() {
+                path.push(i.to_string());
+                walk(child, path, density)?;
+                path.pop();
+            }
+        }
+        Value::String(text) => {
+            if let Some(dimension) = field_dimension(path) {
+                let number = convert(text, dimension, density)
+                    .map_err(|message| format!("{}: {}", path.join("."), message))?;
+                *value = Value::Number(number.into());
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn field_dimension(path: &[String]) -> Option<Dimension> {
+    FIELDS.iter().find_map(|(pattern, dimension)| {
+        let segments = pattern.split('.').collect::<Vec<_>>();
+        let matches = segments.len() == path.len()
+            && segments
+                .iter()
+                .zip(path.iter())
+                .all(|(segment, key)| *segment == "*" || segment == key);
+
+        matches.then_some(*dimension)
+    })
+}
+
+fn convert(text: &str, expected: Dimension, density: f64) -> Result<f64, String> {
+    let (number, unit) =
+        split_quantity(text).ok_or_else(|| format!("'{}' is not a number with a unit", text))?;
+
+    let (_, dimension, scale, offset) = UNITS
+        .iter()
+        .find(|(name, ..)| *name == unit)
+        .ok_or_else(|| format!("unknown unit '{}' in '{}'", unit, text))?;
+
+    let value = number * scale + offset;
+
+    match (*dimension, expected) {
+        (dimension, expected) if dimension == expected => Ok(value),
+        (Dimension::VolumeFlow, Dimension::MassFlow) => Ok(value * density),
+        (dimension, expected) => Err(format!(
+            "'{}' is {}, expected {}",
+            text, dimension, expected
+        )),
+    }
+}
+
+fn split_quantity(text: &str) -> Option<(f64, &str)> {
+    let text = text.trim();
+    let end = text
+        .char_indices()
+        .find(|(i, c)| {
+            let exponent = matches!(c, 'e' | 'E')
+                && text[i + 1..]
+                    .chars()
+                    .next()
+                    .is_some_and(|next| next.is_ascii_digit() || next == '-' || next == '+');
+
+            !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+') || exponent)
+        })
+        .map_or(text.len(), |(i, _)| i);
+
+    let number = text[..end].trim().parse::<f64>().ok()?;
+    let unit = text[end..].trim();
+
+    (!unit.is_empty()).then_some((number, unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(text: &str) -> Vec<String> {
+        text.split('.').map(str::to_string).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn splits_signed_numbers_and_exponents() {
+        assert_eq!(split_quantity("65 degC"), Some((65.0, "degC")));
+        assert_eq!(split_quantity("-5 degC"), Some((-5.0, "degC")));
+        assert_eq!(split_quantity("+2.5m/s"), Some((2.5, "m/s")));
+        assert_eq!(split_quantity("1e-3 s"), Some((0.001, "s")));
+        assert_eq!(split_quantity("2E+2 mm"), Some((200.0, "mm")));
+        assert_eq!(split_quantity(" 3 m "), Some((3.0, "m")));
+    }
+
+    #[test]
+    fn rejects_missing_numbers_or_units() {
+        assert_eq!(split_quantity("12"), None);
+        assert_eq!(split_quantity("degC"), None);
+        assert_eq!(split_quantity(""), None);
+    }
+
+    #[test]
+    fn converts_offset_temperatures() {
+        assert_close(
+            convert("0 K", Dimension::Temperature, 1.0).unwrap(),
+            -273.15,
+        );
+        assert_close(
+            convert("300 K", Dimension::Temperature, 1.0).unwrap(),
+            26.85,
+        );
+        assert_close(convert("32 °F", Dimension::Temperature, 1.0).unwrap(), 0.0);
+        assert_close(
+            convert("-40 degF", Dimension::Temperature, 1.0).unwrap(),
+            -40.0,
+        );
+    }
+
+    #[test]
+    fn converts_scaled_units() {
+        assert_close(convert("1.5 min", Dimension::Time, 1.0).unwrap(), 90.0);
+        assert_close(convert("36 km/h", Dimension::Velocity, 1.0).unwrap(), 10.0);
+        assert_close(
+            convert("1 rad", Dimension::Angle, 1.0).unwrap(),
+            180.0 / std::f64::consts::PI,
+        );
+        assert_close(
+            convert("120 degC/min", Dimension::TemperatureRate, 1.0).unwrap(),
+            2.0,
+        );
+    }
+
+    #[test]
+    fn converts_volume_flows_through_density() {
+        assert_close(
+            convert("6 L/min", Dimension::MassFlow, 1.2e-3).unwrap(),
+            1.2e-4,
+        );
+        assert_close(
+            convert("2 kg/min", Dimension::MassFlow, 1.2e-3).unwrap(),
+            2.0 / 60.0,
+        );
+    }
+
+    #[test]
+    fn converts_volume_flows_with_the_fluid_density() {
+        let mut air: Value = serde_yaml::from_str("actuators: {a: {flow_rate: 2 L/min}}").unwrap();
+        normalize(&mut air, "air.yml").unwrap();
+        let flow_rate = air["actuators"]["a"]["flow_rate"].as_f64().unwrap();
+        assert_close(flow_rate, 2.0 / 60.0 * 0.001204);
+
+        let mut water: Value = serde_yaml::from_str(
+            "simulation: {preset: water}\nactuators: {a: {flow_rate: 6 L/min}}",
+        )
+        .unwrap();
+        normalize(&mut water, "water.yml").unwrap();
+        let flow_rate = water["actuators"]["a"]["flow_rate"].as_f64().unwrap();
+        assert_close(flow_rate, 0.1 * 0.9982);
+    }
+
+    #[test]
+    fn rejects_a_rest_density_with_a_unit() {
+        let mut value: Value =
+            serde_yaml::from_str("simulation: {rest_density: 1.2 kg/m3}").unwrap();
+
+        assert!(normalize(&mut value, "config.yml").is_err());
+    }
+
+    #[test]
+    fn rejects_incompatible_dimensions() {
+        assert_eq!(
+            convert("2 m", Dimension::Time, 1.0),
+            Err("'2 m' is a length, expected a time".to_string())
+        );
+        assert!(convert("1 kg/s", Dimension::VolumeFlow, 1.0).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_units() {
+        assert_eq!(
+            convert("3 furlongs", Dimension::Length, 1.0),
+            Err("unknown unit 'furlongs' in '3 furlongs'".to_string())
+        );
+        assert!(convert("warm", Dimension::Temperature, 1.0).is_err());
+    }
+
+    #[test]
+    fn matches_fields_through_wildcards() {
+        assert_eq!(
+            field_dimension(&path("actuators.a1.schedule.0.start")),
+            Some(Dimension::Time)
+        );
+        assert_eq!(
+            field_dimension(&path("environment.door.schedule.2.end")),
+            Some(Dimension::Time)
+        );
+        assert_eq!(
+            field_dimension(&path("simulation.gravity.1")),
+            Some(Dimension::Acceleration)
+        );
+        assert_eq!(
+            field_dimension(&path("simulation.damping_threshold")),
+            Some(Dimension::Velocity)
+        );
+        assert_eq!(field_dimension(&path("actuators.a1.label")), None);
+        assert_eq!(field_dimension(&path("simulation.gravity")), None);
+    }
+}
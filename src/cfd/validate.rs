@@ -0,0 +1,512 @@
+use crate::cfd::config::{ActuatorKind, Config, FieldFormat, ScheduleConfig};
+use crate::scene::room::RoomMesh;
+use crate::scene::schedule::Schedule;
+use crate::scene::world_map::{Tile, WorldMap, CEILING_HEIGHT};
+use glam::Vec3;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+pub fn validate(config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    validate_environment(config, &mut diagnostics);
+    validate_simulation(config, &mut diagnostics);
+    validate_devices(config, &mut diagnostics);
+    validate_schedules(config, &mut diagnostics);
+    validate_output(config, &mut diagnostics);
+
+    diagnostics
+}
+
+// NaN is not positive either.
+fn is_positive(value: f32) -> bool {
+    value > 0.0
+}
+
+fn validate_environment(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    let cell_size = config.get_cell_size();
+
+    if !is_positive(cell_size) {
+        diagnostics.push(Diagnostic::error(format!(
+            "environment.cell_size must be positive, got {}",
+            cell_size
+        )));
+    }
+
+    if let Some(mesh) = config.get_mesh() {
+        if let Err(e) = RoomMesh::load(mesh) {
+            diagnostics.push(Diagnostic::error(format!(
+                "could not load environment.mesh {}: {}",
+                mesh, e
+            )));
+        }
+    } else if config.get_environment().trim().is_empty() {
+        diagnostics.push(Diagnostic::error(
+            "environment needs a map, a mesh, or both".to_string(),
+        ));
+    }
+
+    let tiles = match WorldMap::parse_tiles(config, config.get_environment()) {
+        Ok(tiles) => tiles,
+        Err(errors) => {
+            errors.iter().for_each(|e| {
+                diagnostics.push(Diagnostic::error(format!("environment.map: {}", e)))
+            });
+            Vec::new()
+        }
+    };
+
+    let inlets = WorldMap::enumerate_tiles(&tiles)
+        .filter(|(_, _, tile)| matches!(tile, Tile::Inlet))
+        .count();
+
+    if inlets > 0 && config.get_inlet().is_none() {
+        diagnostics.push(Diagnostic::warning(format!(
+            "the map has {} inlet tile(s) but no environment.inlet; they will not blow air",
+            inlets
+        )));
+    }
+
+    let mut top = 0.0;
+
+    for (i, layer) in config.get_layers().iter().enumerate() {
+        if !is_positive(layer.height) {
+            diagnostics.push(Diagnostic::error(format!(
+                "environment.layers[{}].height must be positive, got {}",
+                i, layer.height
+            )));
+        }
+
+        if let Err(errors) = WorldMap::parse_tiles(config, &layer.map) {
+            errors.iter().for_each(|e| {
+                diagnostics.push(Diagnostic::error(format!(
+                    "environment.layers[{}].map: {}",
+                    i, e
+                )))
+            });
+        }
+
+        top += layer.height;
+
+        if top > CEILING_HEIGHT {
+            diagnostics.push(Diagnostic::warning(format!(
+                "environment.layers[{}] reaches {} m, above the ceiling at {} m",
+                i, top, CEILING_HEIGHT
+            )));
+        }
+    }
+}
+
+// Loading errors are reported by validate_environment.
+fn room_heights(config: &Config) -> (f32, f32) {
+    let room = config.get_mesh().and_then(|mesh| RoomMesh::load(mesh).ok());
+    WorldMap::room_heights(room.as_ref())
+}
+
+fn validate_simulation(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    let simulation = config.get_simulation_config();
+
+    [
+        ("mass", simulation.mass),
+        ("radius", simulation.radius),
+        ("rest_density", simulation.rest_density),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !is_positive(*value))
+    .for_each(|(name, value)| {
+        diagnostics.push(Diagnostic::error(format!(
+            "simulation.{} must be positive, got {}",
+            name, value
+        )))
+    });
+}
+
+fn validate_devices(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    // Map errors are reported by validate_environment.
+    let tiles = match WorldMap::parse_tiles(config, config.get_environment()) {
+        Ok(tiles) => tiles,
+        Err(_) => return,
+    };
+    let placements = WorldMap::placements(config, &tiles);
+    let targets = WorldMap::locate_devices(config, &placements);
+    let (floor, ceiling) = room_heights(config);
+
+    WorldMap::enumerate_tiles(&tiles).for_each(|(x, z, tile)| {
+        if let Tile::Device(c) = tile {
+            let label = c.to_string();
+
+            if config.get_actuator_by_label(&label).is_none()
+                && config.get_sensor_by_label(&label).is_none()
+            {
+                diagnostics.push(Diagnostic::error(format!(
+                    "device glyph '{}' at line {}, column {} has no actuator or sensor entry",
+                    c,
+                    z as usize + 1,
+                    x as usize + 1
+                )));
+            }
+        }
+    });
+
+    for (i, device) in config.get_devices().iter().enumerate() {
+        let [x, z] = device.tile;
+
+        if config.get_actuator_by_label(&device.label).is_none()
+            && config.get_sensor_by_label(&device.label).is_none()
+        {
+            diagnostics.push(Diagnostic::error(format!(
+                "devices[{}] refers to '{}', which has no actuator or sensor entry",
+                i, device.label
+            )));
+        }
+
+        match tiles.get(z).and_then(|row| row.get(x)) {
+            None | Some(Tile::Empty) if !tiles.is_empty() => {
+                diagnostics.push(Diagnostic::error(format!(
+                    "devices[{}] ('{}') is placed at tile [{}, {}], which is outside the room",
+                    i, device.label, x, z
+                )))
+            }
+            _ => {}
+        }
+    }
+
+    let mut actuators = config.get_actuators().iter().collect::<Vec<_>>();
+    actuators.sort_by_key(|(label, _)| label.as_str());
+
+    for (label, actuator) in actuators {
+        let placed = placements
+            .iter()
+            .filter(|placement| &placement.label == label)
+            .collect::<Vec<_>>();
+
+        if placed.is_empty() {
+            diagnostics.push(Diagnostic::warning(format!(
+                "actuator '{}' is defined but is not placed on the map",
+                label
+            )));
+            continue;
+        }
+
+        if actuator
+            .direction
+            .is_some_and(|direction| direction.length() == 0.0)
+        {
+            diagnostics.push(Diagnostic::error(format!(
+                "actuator '{}' has a zero-length direction",
+                label
+            )));
+        }
+
+        if let Some(target) = &actuator.aim_at {
+            if !targets.contains_key(target) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "actuator '{}' aims at '{}', which is not placed on the map",
+                    label, target
+                )));
+            }
+        }
+
+        if let ActuatorKind::Emitter(emitter) = actuator.kind {
+            if emitter.interval.is_none() && emitter.flow_rate.is_none() {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "actuator '{}' has neither interval nor flow_rate and will not emit",
+                    label
+                )));
+            }
+        }
+
+        for placement in placed {
+            let [x, z] = placement.tile;
+            let position = WorldMap::device_position(
+                placement,
+                actuator.height,
+                actuator.offset,
+                actuator.position,
+                config.get_cell_size(),
+            );
+            let direction =
+                WorldMap::resolve_direction(&tiles, &targets, placement, position, actuator);
+
+            let unaimed = match actuator.kind {
+                ActuatorKind::Emitter(emitter) => {
+                    direction == Vec3::ZERO && emitter.initial_velocity != 0.0
+                }
+                ActuatorKind::Fan(_) => direction == Vec3::ZERO,
+                ActuatorKind::Heater(_) | ActuatorKind::Extractor(_) => false,
+            };
+
+            if unaimed {
+                diagnostics.push(Diagnostic::error(format!(
+                    "actuator '{}' at line {}, column {} has no direction; set direction, facing or aim_at",
+                    label,
+                    z + 1,
+                    x + 1
+                )));
+            }
+        }
+    }
+
+    let mut sensors = config.get_sensors().iter().collect::<Vec<_>>();
+    sensors.sort_by_key(|(label, _)| label.as_str());
+
+    let mut outputs: Vec<(&String, usize)> = Vec::new();
+
+    for (label, sensor) in sensors {
+        let placed = placements
+            .iter()
+            .filter(|placement| &placement.label == label)
+            .collect::<Vec<_>>();
+
+        if placed.is_empty() {
+            diagnostics.push(Diagnostic::warning(format!(
+                "sensor '{}' is defined but is not placed on the map",
+                label
+            )));
+            continue;
+        }
+
+        for placement in placed {
+            let position = WorldMap::device_position(
+                placement,
+                sensor.height,
+                sensor.offset,
+                sensor.position,
+                config.get_cell_size(),
+            );
+            let cell = (position / config.get_cell_size()).floor();
+            let (x, z) = (cell.x as i64, cell.z as i64);
+
+            let outside = matches!(
+                WorldMap::get_tile(&tiles, x, z),
+                None | Some(Tile::Empty | Tile::Wall)
+            );
+
+            if !tiles.is_empty() && outside {
+                diagnostics.push(Diagnostic::error(format!(
+                    "sensor '{}' at ({}, {}, {}) is outside the room",
+                    label, position.x, position.y, position.z
+                )));
+            }
+
+            if position.y < floor || position.y > ceiling {
+                diagnostics.push(Diagnostic::error(format!(
+                    "sensor '{}' height {} is outside the room ({} to {})",
+                    label, position.y, floor, ceiling
+                )));
+            }
+
+            if let Some(output) = &sensor.output {
+                match outputs.iter_mut().find(|(path, _)| *path == output) {
+                    Some((_, count)) => *count += 1,
+                    None => outputs.push((output, 1)),
+                }
+            }
+        }
+
+        if let Some(output) = &sensor.output {
+            let extension = Path::new(output).extension().and_then(|e| e.to_str());
+
+            if extension != Some(sensor.format.extension()) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "sensor '{}' output '{}' is written as {} but does not end in .{}",
+                    label,
+                    output,
+                    sensor.format.extension(),
+                    sensor.format.extension()
+                )));
+            }
+        }
+    }
+
+    outputs
+        .iter()
+        .filter(|(_, count)| *count > 1)
+        .for_each(|(path, count)| {
+            diagnostics.push(Diagnostic::error(format!(
+                "{} sensors write to the same output '{}'",
+                count, path
+            )))
+        });
+}
+
+fn validate_schedules(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    let mut schedules = config
+        .get_actuators()
+        .iter()
+        .filter_map(|(label, actuator)| {
+            let schedule = actuator.schedule.as_ref()?;
+            Some((format!("actuators.{}.schedule", label), schedule))
+        })
+        .collect::<Vec<_>>();
+    schedules.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    if let Some(schedule) = config.get_door().and_then(|door| door.schedule.as_ref()) {
+        schedules.push(("environment.door.schedule".to_string(), schedule));
+    }
+
+    for (path, schedule) in schedules {
+        if let ScheduleConfig::Segments(segments) = schedule {
+            for (i, segment) in segments.iter().enumerate() {
+                if !is_positive(segment.end - segment.start) {
+                    diagnostics.push(Diagnostic::error(format!(
+                        "{}[{}] starts at {} s but ends at {} s",
+                        path, i, segment.start, segment.end
+                    )));
+                }
+            }
+        }
+
+        if let Err(e) = Schedule::new(schedule) {
+            diagnostics.push(Diagnostic::error(format!("{}: {}", path, e)));
+        }
+    }
+}
+
+fn validate_output(config: &Config, diagnostics: &mut Vec<Diagnostic>) {
+    if let Some(particles) = &config.get_output().particles {
+        if !is_positive(particles.interval) {
+            diagnostics.push(Diagnostic::error(format!(
+                "output.particles.interval must be positive, got {}",
+                particles.interval
+            )));
+        }
+    }
+
+    if let Some(fields) = &config.get_output().fields {
+        [
+            ("interval", fields.interval),
+            ("resolution", fields.resolution),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !is_positive(*value))
+        .for_each(|(name, value)| {
+            diagnostics.push(Diagnostic::error(format!(
+                "output.fields.{} must be positive, got {}",
+                name, value
+            )))
+        });
+
+        let (floor, ceiling) = room_heights(config);
+
+        if fields.format == FieldFormat::Csv && fields.heights.is_empty() {
+            diagnostics.push(Diagnostic::error(
+                "output.fields.heights must list at least one height for CSV slices".to_string(),
+            ));
+        }
+
+        fields
+            .heights
+            .iter()
+            .enumerate()
+            .filter(|(_, height)| !(floor..=ceiling).contains(*height))
+            .for_each(|(i, height)| {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "output.fields.heights[{}] is {} m, outside the room ({} to {} m)",
+                    i, height, floor, ceiling
+                )))
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfd::overrides::Overrides;
+    use crate::testing::temp_file;
+
+    fn diagnose(name: &str, contents: &str) -> Vec<String> {
+        let filename = temp_file("validate", name, contents);
+        let config = Config::load(&filename, &Overrides::new()).unwrap();
+
+        validate(&config).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn missing_map_is_an_error() {
+        let diagnostics = diagnose(
+            "empty.yml",
+            "environment: {cell_size: 1.0}\nactuators: {}\nsensors: {}\n",
+        );
+
+        assert_eq!(
+            diagnostics,
+            ["error: environment needs a map, a mesh, or both"]
+        );
+    }
+
+    #[test]
+    fn sensor_outside_the_room_is_an_error() {
+        let room = "environment: |\n  #####\n  #.V.#\n  #####\nactuators: {}\n";
+        let inside = diagnose(
+            "inside.yml",
+            &format!("{}sensors:\n  V: {{height: 1.0}}\n", room),
+        );
+        let outside = diagnose(
+            "outside.yml",
+            &format!(
+                "{}sensors:\n  V: {{height: 1.0, offset: [0.0, 0.0, 1.0]}}\n",
+                room
+            ),
+        );
+
+        assert!(inside.is_empty(), "{:?}", inside);
+        assert_eq!(
+            outside,
+            ["error: sensor 'V' at (2.5, 1, 2.5) is outside the room"]
+        );
+    }
+
+    #[test]
+    fn non_positive_simulation_parameter_is_an_error() {
+        let diagnostics = diagnose(
+            "massless.yml",
+            "environment: |\n  ###\n  #.#\n  ###\nactuators: {}\nsensors: {}\nsimulation: {mass: 0.0, radius: -1.0}\n",
+        );
+
+        assert_eq!(
+            diagnostics,
+            [
+                "error: simulation.mass must be positive, got 0",
+                "error: simulation.radius must be positive, got -1",
+            ]
+        );
+    }
+}
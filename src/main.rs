@@ -1,6 +1,6 @@
 extern crate core;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crate::app::App;
 use crate::cfd::sph::simulation::{SimulationParticle, SPH};
 use crate::gfx::buffer::VertexBuffer;
@@ -11,11 +11,22 @@ use crate::gfx::light::Light;
 use crate::gfx::pipeline::Pipeline;
 use crate::gfx::renderer::Renderer;
 use crate::gfx::texture::DepthTexture;
+use crate::output::checkpoint::{Checkpoint, CheckpointSchedule};
+use crate::output::recorder::SensorRecorder;
+use crate::output::fields::FieldExport;
+use crate::output::manifest::{RunManifest, RunSource};
+use crate::output::snapshot::ParticleSnapshots;
+use crate::output::statistics::RunStatistics;
 use crate::scene::object::particle::{Particle, ParticleInstance};
 use crate::scene::object::plane::Plane;
-use crate::scene::world_map::{Tile, WorldMap};
+use crate::scene::world_map::WorldMap;
 use crate::scene::Scene;
-use crate::cfd::config::Config;
+use crate::cfd::config::{Config, ConfigError};
+use crate::cfd::format;
+use crate::cfd::migrate;
+use crate::cfd::overrides::Overrides;
+use crate::cfd::schema::config_schema;
+use crate::cfd::validate::{validate, Severity};
 use std::env::args;
 use polars::prelude::*;
 use polars::prelude::Series;
@@ -39,7 +50,10 @@ use winit::event::{ElementState, KeyboardInput, WindowEvent};
 mod app;
 mod cfd;
 mod gfx;
+mod output;
 mod scene;
+#[cfg(test)]
+mod testing;
 
 struct FluidSense {
     phong_pipeline: wgpu::RenderPipeline,
@@ -52,6 +66,12 @@ struct FluidSense {
     particle: Particle,
     particle_instance_buffer: VertexBuffer,
     sph: SPH,
+    recorder: SensorRecorder,
+    snapshots: ParticleSnapshots,
+    fields: FieldExport,
+    statistics: RunStatistics,
+    checkpoints: CheckpointSchedule,
+    manifest: RunManifest,
     timer: f32,
     counter: i32,
     df: PolarsResult<DataFrame>,
@@ -59,11 +79,63 @@ struct FluidSense {
 }
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short, long)]
-    config: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, long, required = true)]
+    config: Option<String>,
     #[arg(long, default_value_t = false)]
     headless: bool,
+    /// Override a config value, e.g. `--set simulation.viscosity=0.02`
+    #[arg(long = "set", value_name = "PATH=VALUE", global = true)]
+    set: Vec<String>,
+    /// Merge a YAML patch over the config
+    #[arg(long = "set-file", value_name = "FILE", global = true)]
+    set_file: Vec<String>,
+    /// Continue the run saved in a checkpoint
+    #[arg(long, value_name = "FILE")]
+    resume: Option<String>,
+    /// Save a checkpoint here when the run ends
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<String>,
+    /// Also save the checkpoint every this many simulated seconds
+    #[arg(long, value_name = "SECONDS", requires = "checkpoint")]
+    checkpoint_interval: Option<f32>,
+    /// Write the run manifest here when the run ends
+    #[arg(long, value_name = "FILE", default_value = "manifest.json")]
+    manifest: String,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Validate a config and list its problems
+    Check { config: String },
+    /// Print the JSON Schema of the config format
+    Schema,
+    /// Upgrade a config to the current format version
+    Migrate {
+        config: String,
+        /// Write the upgraded config here instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+impl FluidSense {
+    fn capture(&self) -> Checkpoint {
+        Checkpoint::capture(
+            &self.sph,
+            &self.world_map,
+            &self.recorder,
+            &self.snapshots,
+            &self.fields,
+            &self.statistics,
+            self.timer,
+            self.counter,
+            &self.df,
+        )
+    }
 }
 
 impl App for FluidSense {
@@ -71,7 +143,7 @@ impl App for FluidSense {
         let args = Args::parse();
         let phong_pipeline = Pipeline::phong(renderer);
         let particle_pipeline = Pipeline::particle(renderer);
-        let config = cfd::config::Config::new(&args.config);
+        let config = load_config(&args);
         let mut world_map = WorldMap::new(&config);
         let scene = world_map.build_scene(renderer, &phong_pipeline);
         let (x, z) = scene.user_position();
@@ -87,8 +159,13 @@ impl App for FluidSense {
         let camera_controller = FirstPersonController::new(0.0, 90.0, 4.0, 0.1);
         let light = Light::new(&renderer, &phong_pipeline, camera.position(), Vec3::ONE);
         let particle = Particle::new(renderer);
-        let sph = SPH::new(&config);
-        let particle_instance_buffer = VertexBuffer::new(renderer, sph.get_particle_instances());
+        let mut sph = SPH::new(&config);
+        let mut recorder = SensorRecorder::new(&world_map, &config);
+        let mut snapshots = ParticleSnapshots::new(config.get_output().particles.as_ref());
+        let mut fields = FieldExport::new(config.get_output().fields.as_ref(), &world_map);
+        let mut statistics = RunStatistics::new();
+        let checkpoints = CheckpointSchedule::new(args.checkpoint.clone(), args.checkpoint_interval);
+        let manifest = run_manifest(&args);
 
         let mut timer = 0.0;
         let mut counter = 1;
@@ -109,7 +186,20 @@ impl App for FluidSense {
         let a13 = Column::new("A13".into(), [22.2 as f32]);
         let a14 = Column::new("A14".into(), [22.2 as f32]);
         let a15 = Column::new("A15".into(), [22.3 as f32]);
-        let df =  DataFrame::new(vec![count,a1,a2,a3,a4,a5,a6,a7,a8,a9,a10,a11,a12,a13,a14,a15]);
+        let mut df =  DataFrame::new(vec![count,a1,a2,a3,a4,a5,a6,a7,a8,a9,a10,a11,a12,a13,a14,a15]);
+
+        if let Some(filename) = &args.resume {
+            (timer, counter, df) = load_checkpoint(filename).restore(
+                &mut sph,
+                &mut world_map,
+                &mut recorder,
+                &mut snapshots,
+                &mut fields,
+                &mut statistics,
+            );
+        }
+
+        let particle_instance_buffer = VertexBuffer::new(renderer, sph.get_particle_instances());
 
         Self {
             phong_pipeline,
@@ -122,6 +212,12 @@ impl App for FluidSense {
             particle,
             particle_instance_buffer,
             sph,
+            recorder,
+            snapshots,
+            fields,
+            statistics,
+            checkpoints,
+            manifest,
             timer,
             counter,
             df,
@@ -141,17 +237,18 @@ impl App for FluidSense {
         self.camera_controller.update(&mut self.camera, dt);
         self.light.set_position(self.camera.position());
         self.sph.step(0.001);
-        self.sph.check_particles(&self.world_map);
+        self.world_map.update(&dt);
+        let removed = self.sph.check_particles(&self.world_map);
+        self.statistics.record(&self.world_map, &self.sph, &removed, &dt);
 
         self.world_map
             .get_actuators()
             .iter_mut()
-            .for_each(|(_, actuator)| match actuator.emit_particle(&dt) {
-                None => {}
-                Some(particle) => {
-                    self.sph.add_particle(particle);
-                }
-            });
+            .for_each(|actuator| actuator.actuate(&mut self.sph, &dt));
+
+        self.recorder.record(&self.world_map, &self.sph, &dt);
+        self.snapshots.record(&self.sph, &dt);
+        self.fields.record(&self.sph, &dt);
         
         //let config = cfd::config::Config::new(&args.config);
 
@@ -196,10 +293,10 @@ impl App for FluidSense {
                 let mut A15:f32 = 22.0;
 
         self.sph.get_particles().iter().for_each(|particle| {
-            match self.world_map.get_device_in_position(particle.position) {
+            match self.world_map.get_devices_in_position(particle.position).first() {
                 None => {}
                 Some(label) => {
-                    let sensors = self.world_map.get_sensor_by_label(&label);
+                    let sensors = self.world_map.get_sensor_by_label(label);
                         
                         if particle.position.z >= 2.495 && particle.position.z < 3.165
                         {
@@ -540,9 +637,26 @@ impl App for FluidSense {
             let mut file = std::fs::File::create(file_name).unwrap();
 
             CsvWriter::new(&mut file).finish(&mut df_export).unwrap();
+            self.recorder.finish();
+
+            if let Some(filename) = self.checkpoints.get_path() {
+                save_checkpoint(filename, &self.capture());
+            }
+
+            self.statistics.finish(&mut self.world_map);
+            self.manifest.write(
+                &self.config,
+                &self.statistics,
+                &mut self.world_map,
+                output_files(&self.recorder, &self.snapshots, &self.fields, &self.checkpoints),
+            );
             process::exit(1);
             self.counter +=1;
         }
+
+        if let Some(filename) = self.checkpoints.due(&dt) {
+            save_checkpoint(&filename, &self.capture());
+        }
     }
 
     fn resize(&mut self, width: u32, height: u32) {
@@ -564,9 +678,15 @@ impl App for FluidSense {
 
 fn run_headless() {
     let args = Args::parse();
-    let config = cfd::config::Config::new(&args.config);
+    let config = load_config(&args);
     let mut sph = SPH::new(&config);
     let mut world_map = WorldMap::new(&config);
+    let mut recorder = SensorRecorder::new(&world_map, &config);
+    let mut snapshots = ParticleSnapshots::new(config.get_output().particles.as_ref());
+    let mut fields = FieldExport::new(config.get_output().fields.as_ref(), &world_map);
+    let mut statistics = RunStatistics::new();
+    let mut checkpoints = CheckpointSchedule::new(args.checkpoint.clone(), args.checkpoint_interval);
+    let manifest = run_manifest(&args);
     let mut timer: f32 = 0.0;
     let mut counter: i32 = 1;
     let count = Column::new("Count".into(), [0]);
@@ -587,21 +707,34 @@ fn run_headless() {
     let a15 = Column::new("A15".into(), [22.3 as f32]);
     let mut df =  DataFrame::new(vec![count,a1,a2,a3,a4,a5,a6,a7,a8,a9,a10,a11,a12,a13,a14,a15]);
 
+    if let Some(filename) = &args.resume {
+        (timer, counter, df) = load_checkpoint(filename).restore(
+            &mut sph,
+            &mut world_map,
+            &mut recorder,
+            &mut snapshots,
+            &mut fields,
+            &mut statistics,
+        );
+    }
+
     loop {
         sph.step(0.001);
-        sph.check_particles(&world_map);
 
         let dt = Duration::from_secs_f64(0.016);
 
+        world_map.update(&dt);
+        let removed = sph.check_particles(&world_map);
+        statistics.record(&world_map, &sph, &removed, &dt);
+
         world_map
             .get_actuators()
             .iter_mut()
-            .for_each(|(_, actuator)| match actuator.emit_particle(&dt) {
-                None => {}
-                Some(particle) => {
-                    sph.add_particle(particle);
-                }
-            });
+            .for_each(|actuator| actuator.actuate(&mut sph, &dt));
+
+        recorder.record(&world_map, &sph, &dt);
+        snapshots.record(&sph, &dt);
+        fields.record(&sph, &dt);
 
         if counter < 214
        {     
@@ -644,10 +777,10 @@ fn run_headless() {
                 let mut A15:f32 = 22.0;
 
         sph.get_particles().iter().for_each(|particle| {
-            match world_map.get_device_in_position(particle.position) {
+            match world_map.get_devices_in_position(particle.position).first() {
                 None => {}
                 Some(label) => {
-                    let sensors = world_map.get_sensor_by_label(&label);
+                    let sensors = world_map.get_sensor_by_label(label);
                         
                         if particle.position.z >= 2.495 && particle.position.z < 3.165
                         {
@@ -988,19 +1121,183 @@ fn run_headless() {
             let mut file = std::fs::File::create(file_name).unwrap();
 
             CsvWriter::new(&mut file).finish(&mut df_export).unwrap();
+            recorder.finish();
+
+            if let Some(filename) = checkpoints.get_path() {
+                let checkpoint = Checkpoint::capture(
+                    &sph, &world_map, &recorder, &snapshots, &fields, &statistics, timer, counter,
+                    &df,
+                );
+                save_checkpoint(filename, &checkpoint);
+            }
+
+            statistics.finish(&mut world_map);
+            manifest.write(
+                &config,
+                &statistics,
+                &mut world_map,
+                output_files(&recorder, &snapshots, &fields, &checkpoints),
+            );
             process::exit(1);
             counter +=1;
         }
+
+        if let Some(filename) = checkpoints.due(&dt) {
+            let checkpoint = Checkpoint::capture(
+                &sph, &world_map, &recorder, &snapshots, &fields, &statistics, timer, counter,
+                &df,
+            );
+            save_checkpoint(&filename, &checkpoint);
+        }
+    }
+}
+
+fn run_manifest(args: &Args) -> RunManifest {
+    RunManifest::new(
+        args.manifest.clone(),
+        RunSource {
+            config: args.config.clone().unwrap_or_default(),
+            set: args.set.clone(),
+            set_file: args.set_file.clone(),
+            resume: args.resume.clone(),
+        },
+    )
+}
+
+// Everything a finished run leaves behind, starting with the legacy `saida.csv` table.
+fn output_files(
+    recorder: &SensorRecorder,
+    snapshots: &ParticleSnapshots,
+    fields: &FieldExport,
+    checkpoints: &CheckpointSchedule,
+) -> Vec<String> {
+    let mut files = vec!["saida.csv".to_string()];
+    files.extend(recorder.files());
+    files.extend(snapshots.files());
+    files.extend(fields.files());
+    files.extend(checkpoints.get_path().map(str::to_string));
+
+    files
+}
+
+fn load_checkpoint(filename: &str) -> Checkpoint {
+    match Checkpoint::load(filename) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+fn save_checkpoint(filename: &str, checkpoint: &Checkpoint) {
+    if let Err(e) = checkpoint.save(filename) {
+        eprintln!("Could not save checkpoint: {}", e);
+    }
+}
+
+fn overrides(args: &Args) -> Result<Overrides, ConfigError> {
+    let mut overrides = Overrides::new();
+
+    for filename in args.set_file.iter() {
+        overrides.patch_file(filename)?;
+    }
+
+    overrides.read_env();
+
+    for assignment in args.set.iter() {
+        overrides.set(assignment)?;
+    }
+
+    Ok(overrides)
+}
+
+fn load_config(args: &Args) -> Config {
+    let filename = args.config.as_ref().unwrap();
+
+    let config = match overrides(args).and_then(|overrides| Config::load(filename, &overrides)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    };
+
+    let diagnostics = validate(&config);
+    diagnostics.iter().for_each(|diagnostic| eprintln!("{}", diagnostic));
+
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        process::exit(2);
+    }
+
+    config
+}
+
+fn check(args: &Args, filename: &str) {
+    let config = match overrides(args).and_then(|overrides| Config::load(filename, &overrides)) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let diagnostics = validate(&config);
+    diagnostics.iter().for_each(|diagnostic| println!("{}", diagnostic));
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+
+    println!(
+        "{}: {} error(s), {} warning(s)",
+        filename,
+        errors,
+        diagnostics.len() - errors
+    );
+
+    if errors > 0 {
+        process::exit(1);
+    }
+}
+
+fn schema() {
+    println!("{}", serde_json::to_string_pretty(&config_schema()).unwrap());
+}
+
+fn migrate(filename: &str, output: Option<&str>) {
+    let migrated = format::read_value(filename)
+        .and_then(|mut value| migrate::upgrade(&mut value, filename).map(|_| value));
+
+    let migrated = match migrated {
+        Ok(value) => serde_yaml::to_string(&value).unwrap(),
+        Err(e) => {
+            println!("error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    match output {
+        Some(output) => {
+            if let Err(e) = std::fs::write(output, migrated) {
+                println!("error: could not write {}: {}", output, e);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", migrated),
     }
 }
 
 fn main() {
     let args = Args::parse();
 
-    if args.headless {
-        run_headless();
-    } else {
-        pollster::block_on(app::run::<FluidSense>());
+    match &args.command {
+        Some(Command::Check { config }) => check(&args, config),
+        Some(Command::Schema) => schema(),
+        Some(Command::Migrate { config, output }) => migrate(config, output.as_deref()),
+        None if args.headless => run_headless(),
+        None => pollster::block_on(app::run::<FluidSense>()),
     }
 }
 
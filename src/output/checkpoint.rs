@@ -0,0 +1,352 @@
+use crate::cfd::sph::simulation::{SimulationParticle, SPH};
+use crate::output::fields::{FieldExport, FieldState};
+use crate::output::recorder::{RecorderState, SensorRecorder};
+use crate::output::snapshot::{ParticleSnapshots, SnapshotState};
+use crate::output::statistics::RunStatistics;
+use crate::scene::world_map::{WorldMap, WorldState};
+use polars::prelude::{Column, DataFrame, PolarsResult};
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::time::Duration;
+
+// Written before the encoded state; bump it whenever `Checkpoint` changes shape.
+const MAGIC: &[u8; 8] = b"FSCKPT04";
+
+// Row counts and named columns of the smoothed table behind saida.csv.
+type TableColumns = (Vec<i32>, Vec<(String, Vec<f32>)>);
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(String, std::io::Error),
+    Encode(String, bincode::Error),
+    Format(String, String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(filename, e) => write!(f, "could not open {}: {}", filename, e),
+            CheckpointError::Encode(filename, e) => {
+                write!(f, "could not read checkpoint {}: {}", filename, e)
+            }
+            CheckpointError::Format(filename, message) => {
+                write!(f, "could not read checkpoint {}: {}", filename, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// Everything a run needs to continue where it stopped: particles, actuator timers and random
+/// number streams, sensor readings, output frames, statistics and the smoothed table behind
+/// saida.csv.
+/// Configuration is not included, so a checkpoint can be resumed with different parameters.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    particles: Vec<SimulationParticle>,
+    world: WorldState,
+    recorder: RecorderState,
+    snapshots: SnapshotState,
+    fields: FieldState,
+    statistics: RunStatistics,
+    timer: f32,
+    counter: i32,
+    counts: Vec<i32>,
+    columns: Vec<(String, Vec<f32>)>,
+}
+
+impl Checkpoint {
+    #[allow(clippy::too_many_arguments)]
+    pub fn capture(
+        sph: &SPH,
+        world_map: &WorldMap,
+        recorder: &SensorRecorder,
+        snapshots: &ParticleSnapshots,
+        fields: &FieldExport,
+        statistics: &RunStatistics,
+        timer: f32,
+        counter: i32,
+        table: &PolarsResult<DataFrame>,
+    ) -> Self {
+        let (counts, columns) = match table {
+            Ok(table) => Self::table_columns(table).unwrap_or_default(),
+            Err(_) => Default::default(),
+        };
+
+        Self {
+            particles: sph.get_particles().clone(),
+            world: world_map.save_state(),
+            recorder: recorder.save_state(),
+            snapshots: snapshots.save_state(),
+            fields: fields.save_state(),
+            statistics: statistics.clone(),
+            timer,
+            counter,
+            counts,
+            columns,
+        }
+    }
+
+    /// Puts the saved state into freshly built components and returns the legacy table's
+    /// timer, counter and rows.
+    pub fn restore(
+        self,
+        sph: &mut SPH,
+        world_map: &mut WorldMap,
+        recorder: &mut SensorRecorder,
+        snapshots: &mut ParticleSnapshots,
+        fields: &mut FieldExport,
+        statistics: &mut RunStatistics,
+    ) -> (f32, i32, PolarsResult<DataFrame>) {
+        sph.set_particles(self.particles);
+        world_map.restore_state(self.world);
+        recorder.restore_state(self.recorder);
+        snapshots.restore_state(self.snapshots);
+        fields.restore_state(self.fields);
+        *statistics = self.statistics;
+
+        let mut columns = vec![Column::new("Count".into(), self.counts)];
+        columns.extend(
+            self.columns
+                .into_iter()
+                .map(|(name, values)| Column::new(name.into(), values)),
+        );
+
+        (self.timer, self.counter, DataFrame::new(columns))
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), CheckpointError> {
+        // Write next to the target and rename, so an interrupted save keeps the last checkpoint.
+        let partial = format!("{}.partial", filename);
+        let file =
+            std::fs::File::create(&partial).map_err(|e| CheckpointError::Io(partial.clone(), e))?;
+        let mut writer = BufWriter::new(file);
+
+        writer
+            .write_all(MAGIC)
+            .map_err(|e| CheckpointError::Io(partial.clone(), e))?;
+        bincode::serialize_into(&mut writer, self)
+            .map_err(|e| CheckpointError::Encode(partial.clone(), e))?;
+        writer
+            .flush()
+            .map_err(|e| CheckpointError::Io(partial.clone(), e))?;
+
+        std::fs::rename(&partial, filename)
+            .map_err(|e| CheckpointError::Io(filename.to_string(), e))
+    }
+
+    pub fn load(filename: &str) -> Result<Self, CheckpointError> {
+        let file = std::fs::File::open(filename)
+            .map_err(|e| CheckpointError::Io(filename.to_string(), e))?;
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 8];
+
+        reader
+            .read_exact(&mut magic)
+            .map_err(|e| CheckpointError::Io(filename.to_string(), e))?;
+
+        if &magic != MAGIC {
+            return Err(CheckpointError::Format(
+                filename.to_string(),
+                "not a checkpoint of this version of fluid-sense".to_string(),
+            ));
+        }
+
+        bincode::deserialize_from(reader)
+            .map_err(|e| CheckpointError::Encode(filename.to_string(), e))
+    }
+
+    fn table_columns(table: &DataFrame) -> PolarsResult<TableColumns> {
+        let counts = table
+            .column("Count")?
+            .i32()?
+            .into_iter()
+            .map(|count| count.unwrap_or_default())
+            .collect();
+
+        let columns = table
+            .get_columns()
+            .iter()
+            .filter(|column| column.name().as_str() != "Count")
+            .map(|column| {
+                let values = column
+                    .f32()?
+                    .into_iter()
+                    .map(|value| value.unwrap_or_default())
+                    .collect();
+
+                Ok((column.name().to_string(), values))
+            })
+            .collect::<PolarsResult<_>>()?;
+
+        Ok((counts, columns))
+    }
+}
+
+/// When to write checkpoints: at the end of the run and, optionally, every `interval`
+/// simulated seconds.
+pub struct CheckpointSchedule {
+    path: Option<String>,
+    interval: Option<f32>,
+    timer: f32,
+}
+
+impl CheckpointSchedule {
+    pub fn new(path: Option<String>, interval: Option<f32>) -> Self {
+        Self {
+            path,
+            interval,
+            timer: 0.0,
+        }
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Advances the timer and returns the path to write to when a periodic checkpoint is due.
+    pub fn due(&mut self, dt: &Duration) -> Option<String> {
+        let interval = self.interval?;
+        self.timer += dt.as_secs_f32();
+
+        if self.timer < interval {
+            return None;
+        }
+
+        self.timer = 0.0;
+        self.path.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfd::config::{Config, FluidType};
+    use crate::cfd::overrides::Overrides;
+    use crate::testing::temp_file;
+    use glam::Vec3;
+
+    const ROOM: &str = "\
+environment: |
+  #####
+  #.aV#
+  #####
+actuators:
+  a:
+    height: 1.0
+    direction: [1.0, 0.0, 0.0]
+    fluid_type: Gaseous
+    temperature: 65.0
+    initial_velocity: 5.0
+    interval: 0.05
+    range: [0.2, 0.2, 0.2]
+    particle: {color: [0.0, 0.0, 1.0], size: 0.02}
+sensors:
+  V: {height: 1.0}
+seed: 7
+";
+
+    struct Run {
+        sph: SPH,
+        world_map: WorldMap,
+        recorder: SensorRecorder,
+        snapshots: ParticleSnapshots,
+        fields: FieldExport,
+        statistics: RunStatistics,
+    }
+
+    impl Run {
+        fn new(config: &Config) -> Self {
+            let world_map = WorldMap::new(config);
+
+            Self {
+                sph: SPH::new(config),
+                recorder: SensorRecorder::new(&world_map, config),
+                snapshots: ParticleSnapshots::new(config.get_output().particles.as_ref()),
+                fields: FieldExport::new(config.get_output().fields.as_ref(), &world_map),
+                statistics: RunStatistics::new(),
+                world_map,
+            }
+        }
+
+        fn capture(&self, timer: f32, counter: i32, table: &PolarsResult<DataFrame>) -> Checkpoint {
+            Checkpoint::capture(
+                &self.sph,
+                &self.world_map,
+                &self.recorder,
+                &self.snapshots,
+                &self.fields,
+                &self.statistics,
+                timer,
+                counter,
+                table,
+            )
+        }
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let filename = temp_file("checkpoint", "room.yml", ROOM);
+        let config = Config::load(&filename, &Overrides::new()).unwrap();
+
+        let mut run = Run::new(&config);
+        run.sph.set_particles(vec![
+            SimulationParticle::new(
+                Vec3::new(1.5, 1.0, 1.5),
+                Vec3::new(0.5, 0.0, 0.0),
+                40.0,
+                FluidType::Gaseous,
+                0.02,
+                Vec3::Z,
+            ),
+            SimulationParticle::new(
+                Vec3::new(1.6, 1.1, 1.5),
+                Vec3::ZERO,
+                20.0,
+                FluidType::Gaseous,
+                0.02,
+                Vec3::Z,
+            ),
+        ]);
+        run.world_map.update(&Duration::from_millis(300));
+        run.sph.step(0.01);
+
+        let table = DataFrame::new(vec![
+            Column::new("Count".into(), vec![0, 1]),
+            Column::new("A1".into(), vec![20.5_f32, 21.25]),
+        ]);
+        let saved = run.capture(1.5, 2, &table);
+        let path = temp_file("checkpoint", "run.ckpt", "");
+        saved.save(&path).unwrap();
+
+        let mut resumed = Run::new(&config);
+        let (timer, counter, restored) = Checkpoint::load(&path).unwrap().restore(
+            &mut resumed.sph,
+            &mut resumed.world_map,
+            &mut resumed.recorder,
+            &mut resumed.snapshots,
+            &mut resumed.fields,
+            &mut resumed.statistics,
+        );
+
+        assert_eq!((timer, counter), (1.5, 2));
+        assert!(restored.as_ref().unwrap().equals(table.as_ref().unwrap()));
+        assert_eq!(
+            bincode::serialize(&resumed.capture(timer, counter, &restored)).unwrap(),
+            bincode::serialize(&saved).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_file("checkpoint", "other.ckpt", "FSCKPT01 and then some");
+
+        assert!(matches!(
+            Checkpoint::load(&path),
+            Err(CheckpointError::Format(..))
+        ));
+    }
+}
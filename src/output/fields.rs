@@ -0,0 +1,300 @@
+use crate::cfd::config::{FieldFormat, FieldOutputConfig, FieldQuantity};
+use crate::cfd::sph::simulation::SPH;
+use crate::scene::world_map::WorldMap;
+use glam::Vec3;
+use polars::prelude::{Column, CsvWriter, DataFrame, SerWriter};
+use serde::{Deserialize, Serialize};
+
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FieldFrame {
+    time: f32,
+    files: Vec<String>,
+}
+
+/// Frames written so far, kept in checkpoints so a resumed run extends the same index.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldState {
+    time: f32,
+    timer: f32,
+    frames: Vec<FieldFrame>,
+}
+
+// Describes the grid for readers of the frames; rewritten with every frame.
+#[derive(Serialize)]
+struct FieldIndex<'a> {
+    origin: Vec3,
+    spacing: f32,
+    shape: [usize; 3],
+    axes: &'a str,
+    heights: &'a [f32],
+    quantities: &'a [FieldQuantity],
+    frames: &'a [FieldFrame],
+}
+
+/// Interpolates particle quantities onto a regular grid over the room every `interval`
+/// simulated seconds. Grid points sit at cell centers; points without particles nearby read
+/// NaN, except concentration, which reads zero.
+pub struct FieldExport {
+    config: Option<FieldOutputConfig>,
+    origin: Vec3,
+    shape: [usize; 3],
+    state: FieldState,
+}
+
+impl FieldExport {
+    pub fn new(config: Option<&FieldOutputConfig>, world_map: &WorldMap) -> Self {
+        let (min, max) = world_map.extent();
+        let shape = match config {
+            Some(config) if config.resolution > 0.0 => {
+                let cells = ((max - min) / config.resolution).ceil().max(Vec3::ONE);
+                [cells.x as usize, cells.y as usize, cells.z as usize]
+            }
+            _ => [0; 3],
+        };
+
+        if let Some(config) = config {
+            if let Err(e) = std::fs::create_dir_all(&config.directory) {
+                eprintln!("Could not create {}: {}", config.directory, e);
+            }
+        }
+
+        Self {
+            config: config.cloned(),
+            origin: min,
+            shape,
+            state: FieldState::default(),
+        }
+    }
+
+    pub fn record(&mut self, sph: &SPH, dt: &Duration) {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return,
+        };
+
+        self.state.time += dt.as_secs_f32();
+        self.state.timer += dt.as_secs_f32();
+
+        if self.state.timer < config.interval {
+            return;
+        }
+
+        self.state.timer = 0.0;
+
+        let frame = self.state.frames.len();
+        let written = match config.format {
+            FieldFormat::Npy => self.write_volumes(config, sph, frame),
+            FieldFormat::Csv => self.write_slices(config, sph, frame),
+        };
+
+        match written {
+            Ok(files) => self.state.frames.push(FieldFrame {
+                time: self.state.time,
+                files,
+            }),
+            Err(e) => {
+                eprintln!("Could not write fields to {}: {}", config.directory, e);
+                return;
+            }
+        }
+
+        if let Err(e) = self.write_index(config) {
+            eprintln!(
+                "Could not write the field index to {}: {}",
+                config.directory, e
+            );
+        }
+    }
+
+    pub fn save_state(&self) -> FieldState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: FieldState) {
+        self.state = state;
+    }
+
+    /// Paths of the index and the frames written so far.
+    pub fn files(&self) -> Vec<String> {
+        let (config, frames) = match &self.config {
+            Some(config) if !self.state.frames.is_empty() => (config, &self.state.frames),
+            _ => return Vec::new(),
+        };
+
+        std::iter::once("fields.json")
+            .chain(
+                frames
+                    .iter()
+                    .flat_map(|frame| frame.files.iter().map(String::as_str)),
+            )
+            .map(|file| {
+                Path::new(&config.directory)
+                    .join(file)
+                    .display()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn point(&self, x: usize, y: usize, z: usize, resolution: f32) -> Vec3 {
+        self.origin + (Vec3::new(x as f32, y as f32, z as f32) + 0.5) * resolution
+    }
+
+    fn sample(sph: &SPH, position: Vec3, quantities: &[FieldQuantity]) -> Vec<f32> {
+        let sample = sph.interpolate(position);
+
+        quantities
+            .iter()
+            .map(|quantity| match (quantity, sample) {
+                (FieldQuantity::Temperature, Some(sample)) => sample.temperature,
+                (FieldQuantity::Speed, Some(sample)) => sample.velocity.length(),
+                (FieldQuantity::Concentration, Some(sample)) => sample.density,
+                (FieldQuantity::Concentration, None) => 0.0,
+                (_, None) => f32::NAN,
+            })
+            .collect()
+    }
+
+    // Arrays are indexed [x][y][z], in C order.
+    fn write_volumes(
+        &self,
+        config: &FieldOutputConfig,
+        sph: &SPH,
+        frame: usize,
+    ) -> std::io::Result<Vec<String>> {
+        let [nx, ny, nz] = self.shape;
+        let mut volumes = vec![Vec::with_capacity(nx * ny * nz); config.quantities.len()];
+
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    let position = self.point(x, y, z, config.resolution);
+                    let values = Self::sample(sph, position, &config.quantities);
+
+                    volumes
+                        .iter_mut()
+                        .zip(values)
+                        .for_each(|(volume, value)| volume.push(value));
+                }
+            }
+        }
+
+        config
+            .quantities
+            .iter()
+            .zip(volumes)
+            .map(|(quantity, volume)| {
+                let file = format!("{}_{:05}.npy", Self::name(*quantity), frame);
+                Self::write_npy(
+                    &Path::new(&config.directory).join(&file),
+                    self.shape,
+                    &volume,
+                )?;
+
+                Ok(file)
+            })
+            .collect()
+    }
+
+    fn write_slices(
+        &self,
+        config: &FieldOutputConfig,
+        sph: &SPH,
+        frame: usize,
+    ) -> std::io::Result<Vec<String>> {
+        let [nx, _, nz] = self.shape;
+
+        config
+            .heights
+            .iter()
+            .map(|height| {
+                let mut xs = Vec::with_capacity(nx * nz);
+                let mut zs = Vec::with_capacity(nx * nz);
+                let mut values = vec![Vec::with_capacity(nx * nz); config.quantities.len()];
+
+                for x in 0..nx {
+                    for z in 0..nz {
+                        let position = self.point(x, 0, z, config.resolution);
+                        let position = Vec3::new(position.x, *height, position.z);
+
+                        xs.push(position.x);
+                        zs.push(position.z);
+                        values
+                            .iter_mut()
+                            .zip(Self::sample(sph, position, &config.quantities))
+                            .for_each(|(column, value)| column.push(value));
+                    }
+                }
+
+                let mut columns = vec![Column::new("x".into(), xs), Column::new("z".into(), zs)];
+                columns.extend(
+                    config
+                        .quantities
+                        .iter()
+                        .zip(values)
+                        .map(|(quantity, values)| {
+                            Column::new(Self::name(*quantity).into(), values)
+                        }),
+                );
+
+                let file = format!("slice_{}m_{:05}.csv", height, frame);
+                let mut output = std::fs::File::create(Path::new(&config.directory).join(&file))?;
+                let mut df = DataFrame::new(columns).unwrap();
+                CsvWriter::new(&mut output)
+                    .finish(&mut df)
+                    .map_err(std::io::Error::other)?;
+
+                Ok(file)
+            })
+            .collect()
+    }
+
+    fn write_index(&self, config: &FieldOutputConfig) -> std::io::Result<()> {
+        let index = FieldIndex {
+            origin: self.origin,
+            spacing: config.resolution,
+            shape: self.shape,
+            axes: "x, y, z",
+            heights: &config.heights,
+            quantities: &config.quantities,
+            frames: &self.state.frames,
+        };
+
+        let contents = serde_json::to_string_pretty(&index).unwrap();
+        std::fs::write(Path::new(&config.directory).join("fields.json"), contents)
+    }
+
+    // NumPy format 1.0: magic, header length, a Python dict literal padded so the data starts
+    // on a 64-byte boundary, then little-endian float32 values.
+    fn write_npy(path: &Path, shape: [usize; 3], values: &[f32]) -> std::io::Result<()> {
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            shape[0], shape[1], shape[2]
+        );
+        let padding = 64 - (10 + header.len() + 1) % 64;
+        header.push_str(&" ".repeat(padding % 64));
+        header.push('\n');
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(b"\x93NUMPY\x01\x00")?;
+        file.write_all(&(header.len() as u16).to_le_bytes())?;
+        file.write_all(header.as_bytes())?;
+        values
+            .iter()
+            .try_for_each(|value| file.write_all(&value.to_le_bytes()))?;
+
+        file.flush()
+    }
+
+    fn name(quantity: FieldQuantity) -> &'static str {
+        match quantity {
+            FieldQuantity::Temperature => "temperature",
+            FieldQuantity::Speed => "speed",
+            FieldQuantity::Concentration => "concentration",
+        }
+    }
+}
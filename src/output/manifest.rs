@@ -0,0 +1,80 @@
+use crate::cfd::config::Config;
+use crate::output::statistics::{RunStatistics, RunSummary};
+use crate::scene::world_map::WorldMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Config file, overrides and checkpoint a run was started from.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSource {
+    pub config: String,
+    pub set: Vec<String>,
+    pub set_file: Vec<String>,
+    pub resume: Option<String>,
+}
+
+// Everything needed to trace an output back to the run that wrote it and to repeat that run.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    version: &'static str,
+    revision: Option<&'static str>,
+    source: &'a RunSource,
+    config_hash: String,
+    seed: u64,
+    config: serde_json::Value,
+    started: DateTime<Utc>,
+    finished: DateTime<Utc>,
+    wall_clock_seconds: f64,
+    statistics: RunSummary,
+    outputs: Vec<String>,
+}
+
+/// Writes a JSON manifest when the run ends, recording the resolved config, the seed, the
+/// software version, how long the run took, what it simulated and which files it wrote.
+pub struct RunManifest {
+    path: String,
+    source: RunSource,
+    started: DateTime<Utc>,
+}
+
+impl RunManifest {
+    pub fn new(path: String, source: RunSource) -> Self {
+        Self {
+            path,
+            source,
+            started: Utc::now(),
+        }
+    }
+
+    pub fn write(
+        &self,
+        config: &Config,
+        statistics: &RunStatistics,
+        world_map: &mut WorldMap,
+        outputs: Vec<String>,
+    ) {
+        let finished = Utc::now();
+        let manifest = Manifest {
+            version: env!("CARGO_PKG_VERSION"),
+            revision: option_env!("FLUID_SENSE_REVISION"),
+            source: &self.source,
+            config_hash: config.digest(),
+            seed: config.get_seed(),
+            // Goes through a `Value` so the maps come out sorted.
+            config: serde_json::to_value(config).unwrap_or_default(),
+            started: self.started,
+            finished,
+            wall_clock_seconds: (finished - self.started).num_milliseconds() as f64 / 1000.0,
+            statistics: statistics.summary(world_map),
+            outputs,
+        };
+
+        let written = serde_json::to_string_pretty(&manifest)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| std::fs::write(&self.path, contents));
+
+        if let Err(e) = written {
+            eprintln!("Could not write manifest {}: {}", self.path, e);
+        }
+    }
+}
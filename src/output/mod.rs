@@ -0,0 +1,6 @@
+pub mod checkpoint;
+pub mod fields;
+pub mod manifest;
+pub mod recorder;
+pub mod snapshot;
+pub mod statistics;
@@ -0,0 +1,242 @@
+use crate::cfd::config::{Config, TableFormat};
+use crate::cfd::sph::simulation::SPH;
+use crate::scene::world_map::WorldMap;
+use polars::prelude::{
+    Column, CsvWriter, DataFrame, IpcWriter, KeyValueMetadata, ParquetWriter, SerWriter,
+};
+use serde::{Deserialize, Serialize};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+struct SensorOutput {
+    index: usize,
+    label: String,
+    path: String,
+    format: TableFormat,
+    interval: Option<f32>,
+    timer: f32,
+    columns: Vec<String>,
+    time: Vec<f32>,
+    values: Vec<Vec<Option<f32>>>,
+}
+
+impl SensorOutput {
+    fn to_data_frame(&self) -> DataFrame {
+        let mut columns = vec![Column::new("time".into(), &self.time)];
+
+        for (i, name) in self.columns.iter().enumerate() {
+            let values: Vec<Option<f32>> = self.values.iter().map(|row| row[i]).collect();
+            columns.push(Column::new(name.into(), values));
+        }
+
+        DataFrame::new(columns).unwrap()
+    }
+
+    fn write_jsonl(&self, file: File, metadata: &[(String, String)]) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(file);
+        let metadata = metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), serde_json::Value::String(value.clone())))
+            .collect::<serde_json::Map<_, _>>();
+
+        writeln!(writer, "{}", serde_json::json!({ "metadata": metadata }))?;
+
+        // Fields are written by hand to keep the column order.
+        for (time, row) in self.time.iter().zip(self.values.iter()) {
+            let fields = std::iter::once(("time", Some(*time)))
+                .chain(
+                    self.columns
+                        .iter()
+                        .map(String::as_str)
+                        .zip(row.iter().copied()),
+                )
+                .map(|(name, value)| {
+                    Ok(format!(
+                        "{}:{}",
+                        serde_json::to_string(name)?,
+                        serde_json::to_string(&value)?
+                    ))
+                })
+                .collect::<serde_json::Result<Vec<_>>>()?;
+
+            writeln!(writer, "{{{}}}", fields.join(","))?;
+        }
+
+        writer.flush()
+    }
+}
+
+pub struct SensorRecorder {
+    time: f32,
+    outputs: Vec<SensorOutput>,
+    metadata: Vec<(String, String)>,
+}
+
+/// Readings taken so far, kept in checkpoints so a resumed run writes complete outputs.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecorderState {
+    time: f32,
+    outputs: Vec<OutputState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OutputState {
+    label: String,
+    timer: f32,
+    time: Vec<f32>,
+    values: Vec<Vec<Option<f32>>>,
+}
+
+impl SensorRecorder {
+    pub fn new(world_map: &WorldMap, config: &Config) -> Self {
+        let outputs = world_map
+            .get_sensors()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, sensor)| {
+                sensor.get_output().map(|path| SensorOutput {
+                    index,
+                    label: sensor.get_label().to_string(),
+                    path: path.clone(),
+                    format: sensor.get_format(),
+                    interval: sensor.get_interval(),
+                    timer: 0.0,
+                    columns: sensor.columns(),
+                    time: Vec::new(),
+                    values: Vec::new(),
+                })
+            })
+            .collect();
+
+        // Stored with every output that has room for it, so a table can be traced back to the
+        // run that produced it.
+        let metadata = vec![
+            ("config_hash".to_string(), config.digest()),
+            ("seed".to_string(), config.get_seed().to_string()),
+            (
+                "simulation".to_string(),
+                serde_json::to_string(config.get_simulation_config()).unwrap_or_default(),
+            ),
+            ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+        ];
+
+        Self {
+            time: 0.0,
+            outputs,
+            metadata,
+        }
+    }
+
+    pub fn record(&mut self, world_map: &WorldMap, sph: &SPH, dt: &Duration) {
+        self.time += dt.as_secs_f32();
+
+        for output in self.outputs.iter_mut() {
+            output.timer += dt.as_secs_f32();
+
+            if let Some(interval) = output.interval {
+                if output.timer < interval {
+                    continue;
+                }
+            }
+
+            output.timer = 0.0;
+
+            if let Some(sensor) = world_map.get_sensors().get(output.index) {
+                output.time.push(self.time);
+                output.values.push(sensor.measure(sph));
+            }
+        }
+    }
+
+    pub fn save_state(&self) -> RecorderState {
+        RecorderState {
+            time: self.time,
+            outputs: self
+                .outputs
+                .iter()
+                .map(|output| OutputState {
+                    label: output.label.clone(),
+                    timer: output.timer,
+                    time: output.time.clone(),
+                    values: output.values.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores readings by sensor label. Readings whose columns no longer match the sensor's
+    /// quantities are dropped.
+    pub fn restore_state(&mut self, state: RecorderState) {
+        self.time = state.time;
+
+        for saved in state.outputs {
+            let output = self
+                .outputs
+                .iter_mut()
+                .find(|output| output.label == saved.label);
+
+            if let Some(output) = output {
+                output.timer = saved.timer;
+
+                if saved
+                    .values
+                    .iter()
+                    .all(|row| row.len() == output.columns.len())
+                {
+                    output.time = saved.time;
+                    output.values = saved.values;
+                }
+            }
+        }
+    }
+
+    pub fn files(&self) -> Vec<String> {
+        self.outputs
+            .iter()
+            .map(|output| output.path.clone())
+            .collect()
+    }
+
+    pub fn finish(&self) {
+        for output in self.outputs.iter() {
+            if let Err(e) = self.write(output) {
+                eprintln!(
+                    "Could not write output of sensor {} to {}: {}",
+                    output.label, output.path, e
+                );
+            }
+        }
+    }
+
+    fn write(&self, output: &SensorOutput) -> std::io::Result<()> {
+        let mut df = output.to_data_frame();
+        let mut file = File::create(&output.path)?;
+        let mut metadata = self.metadata.clone();
+        metadata.push(("sensor".to_string(), output.label.clone()));
+
+        match output.format {
+            TableFormat::Csv => CsvWriter::new(&mut file)
+                .finish(&mut df)
+                .map_err(std::io::Error::other),
+            TableFormat::Parquet => ParquetWriter::new(&mut file)
+                .with_key_value_metadata(Some(KeyValueMetadata::from_static(metadata)))
+                .finish(&mut df)
+                .map(|_| ())
+                .map_err(std::io::Error::other),
+            TableFormat::Arrow => {
+                let mut writer = IpcWriter::new(&mut file);
+                writer.set_custom_schema_metadata(Arc::new(
+                    metadata
+                        .into_iter()
+                        .map(|(key, value)| (key.into(), value.into()))
+                        .collect(),
+                ));
+                writer.finish(&mut df).map_err(std::io::Error::other)
+            }
+            TableFormat::Jsonl => output.write_jsonl(file, &metadata),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use crate::cfd::config::{FluidType, ParticleOutputConfig, SnapshotFormat};
+use crate::cfd::sph::simulation::{SimulationParticle, SPH};
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+/// Frames written so far, kept in checkpoints so a resumed run extends the same collection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapshotState {
+    time: f32,
+    timer: f32,
+    frames: Vec<(f32, String)>,
+}
+
+/// Writes the particles every `interval` simulated seconds as VTK files, listed with their
+/// times in a `particles.pvd` collection that ParaView opens as a time series.
+pub struct ParticleSnapshots {
+    config: Option<ParticleOutputConfig>,
+    state: SnapshotState,
+}
+
+impl ParticleSnapshots {
+    pub fn new(config: Option<&ParticleOutputConfig>) -> Self {
+        if let Some(config) = config {
+            if let Err(e) = std::fs::create_dir_all(&config.directory) {
+                eprintln!("Could not create {}: {}", config.directory, e);
+            }
+        }
+
+        Self {
+            config: config.cloned(),
+            state: SnapshotState::default(),
+        }
+    }
+
+    pub fn record(&mut self, sph: &SPH, dt: &Duration) {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return,
+        };
+
+        self.state.time += dt.as_secs_f32();
+        self.state.timer += dt.as_secs_f32();
+
+        if self.state.timer < config.interval {
+            return;
+        }
+
+        self.state.timer = 0.0;
+
+        let extension = match config.format {
+            SnapshotFormat::Vtk => "vtk",
+            SnapshotFormat::Vtu => "vtu",
+        };
+        let file = format!("particles_{:05}.{}", self.state.frames.len(), extension);
+        let contents = match config.format {
+            SnapshotFormat::Vtk => Self::to_vtk(sph.get_particles()),
+            SnapshotFormat::Vtu => Self::to_vtu(sph.get_particles()),
+        };
+
+        let path = Path::new(&config.directory).join(&file);
+
+        if let Err(e) = std::fs::write(&path, contents) {
+            eprintln!("Could not write particles to {}: {}", path.display(), e);
+            return;
+        }
+
+        self.state.frames.push((self.state.time, file));
+
+        // Rewritten with every frame, so an interrupted run can still be opened.
+        let collection = Path::new(&config.directory).join("particles.pvd");
+
+        if let Err(e) = std::fs::write(&collection, self.to_pvd()) {
+            eprintln!("Could not write {}: {}", collection.display(), e);
+        }
+    }
+
+    pub fn save_state(&self) -> SnapshotState {
+        self.state.clone()
+    }
+
+    pub fn restore_state(&mut self, state: SnapshotState) {
+        self.state = state;
+    }
+
+    /// Paths of the collection and the frames written so far.
+    pub fn files(&self) -> Vec<String> {
+        let (config, frames) = match &self.config {
+            Some(config) if !self.state.frames.is_empty() => (config, &self.state.frames),
+            _ => return Vec::new(),
+        };
+
+        std::iter::once("particles.pvd")
+            .chain(frames.iter().map(|(_, file)| file.as_str()))
+            .map(|file| {
+                Path::new(&config.directory)
+                    .join(file)
+                    .display()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn fluid_code(fluid_type: FluidType) -> i32 {
+        match fluid_type {
+            FluidType::Gaseous => 0,
+            FluidType::Liquid => 1,
+        }
+    }
+
+    fn to_vtk(particles: &[SimulationParticle]) -> String {
+        let mut vtk = String::new();
+        let count = particles.len();
+
+        writeln!(vtk, "# vtk DataFile Version 3.0").unwrap();
+        writeln!(vtk, "fluid-sense particles").unwrap();
+        writeln!(vtk, "ASCII").unwrap();
+        writeln!(vtk, "DATASET POLYDATA").unwrap();
+        writeln!(vtk, "POINTS {} float", count).unwrap();
+        particles.iter().for_each(|p| {
+            writeln!(vtk, "{} {} {}", p.position.x, p.position.y, p.position.z).unwrap()
+        });
+
+        writeln!(vtk, "VERTICES {} {}", count, count * 2).unwrap();
+        (0..count).for_each(|i| writeln!(vtk, "1 {}", i).unwrap());
+
+        writeln!(vtk, "POINT_DATA {}", count).unwrap();
+        writeln!(vtk, "VECTORS velocity float").unwrap();
+        particles.iter().for_each(|p| {
+            let velocity = p.get_velocity();
+            writeln!(vtk, "{} {} {}", velocity.x, velocity.y, velocity.z).unwrap()
+        });
+
+        for (name, values) in [
+            (
+                "density",
+                particles
+                    .iter()
+                    .map(|p| p.get_density())
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "temperature",
+                particles.iter().map(|p| p.temperature).collect(),
+            ),
+        ] {
+            writeln!(vtk, "SCALARS {} float 1", name).unwrap();
+            writeln!(vtk, "LOOKUP_TABLE default").unwrap();
+            values
+                .iter()
+                .for_each(|value| writeln!(vtk, "{}", value).unwrap());
+        }
+
+        writeln!(vtk, "SCALARS fluid_type int 1").unwrap();
+        writeln!(vtk, "LOOKUP_TABLE default").unwrap();
+        particles
+            .iter()
+            .for_each(|p| writeln!(vtk, "{}", Self::fluid_code(p.get_fluid_type())).unwrap());
+
+        vtk
+    }
+
+    fn to_vtu(particles: &[SimulationParticle]) -> String {
+        let mut vtu = String::new();
+        let count = particles.len();
+        let join = |values: Vec<String>| values.join(" ");
+
+        writeln!(vtu, r#"<?xml version="1.0"?>"#).unwrap();
+        writeln!(
+            vtu,
+            r#"<VTKFile type="UnstructuredGrid" version="0.1" byte_order="LittleEndian">"#
+        )
+        .unwrap();
+        writeln!(vtu, "<UnstructuredGrid>").unwrap();
+        writeln!(
+            vtu,
+            r#"<Piece NumberOfPoints="{0}" NumberOfCells="{0}">"#,
+            count
+        )
+        .unwrap();
+
+        writeln!(vtu, "<Points>").unwrap();
+        writeln!(
+            vtu,
+            r#"<DataArray type="Float32" NumberOfComponents="3" format="ascii">{}</DataArray>"#,
+            join(
+                particles
+                    .iter()
+                    .map(|p| format!("{} {} {}", p.position.x, p.position.y, p.position.z))
+                    .collect()
+            )
+        )
+        .unwrap();
+        writeln!(vtu, "</Points>").unwrap();
+
+        // One vertex cell per particle.
+        writeln!(vtu, "<Cells>").unwrap();
+        writeln!(
+            vtu,
+            r#"<DataArray type="Int32" Name="connectivity" format="ascii">{}</DataArray>"#,
+            join((0..count).map(|i| i.to_string()).collect())
+        )
+        .unwrap();
+        writeln!(
+            vtu,
+            r#"<DataArray type="Int32" Name="offsets" format="ascii">{}</DataArray>"#,
+            join((1..=count).map(|i| i.to_string()).collect())
+        )
+        .unwrap();
+        writeln!(
+            vtu,
+            r#"<DataArray type="UInt8" Name="types" format="ascii">{}</DataArray>"#,
+            join(vec!["1".to_string(); count])
+        )
+        .unwrap();
+        writeln!(vtu, "</Cells>").unwrap();
+
+        writeln!(
+            vtu,
+            r#"<PointData Scalars="temperature" Vectors="velocity">"#
+        )
+        .unwrap();
+        writeln!(
+            vtu,
+            r#"<DataArray type="Float32" Name="velocity" NumberOfComponents="3" format="ascii">{}</DataArray>"#,
+            join(particles
+                .iter()
+                .map(|p| {
+                    let velocity = p.get_velocity();
+                    format!("{} {} {}", velocity.x, velocity.y, velocity.z)
+                })
+                .collect())
+        )
+        .unwrap();
+
+        for (name, values) in [
+            (
+                "density",
+                particles
+                    .iter()
+                    .map(|p| p.get_density().to_string())
+                    .collect(),
+            ),
+            (
+                "temperature",
+                particles
+                    .iter()
+                    .map(|p| p.temperature.to_string())
+                    .collect(),
+            ),
+        ] {
+            writeln!(
+                vtu,
+                r#"<DataArray type="Float32" Name="{}" format="ascii">{}</DataArray>"#,
+                name,
+                join(values)
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            vtu,
+            r#"<DataArray type="Int32" Name="fluid_type" format="ascii">{}</DataArray>"#,
+            join(
+                particles
+                    .iter()
+                    .map(|p| Self::fluid_code(p.get_fluid_type()).to_string())
+                    .collect()
+            )
+        )
+        .unwrap();
+        writeln!(vtu, "</PointData>").unwrap();
+
+        writeln!(vtu, "</Piece>").unwrap();
+        writeln!(vtu, "</UnstructuredGrid>").unwrap();
+        writeln!(vtu, "</VTKFile>").unwrap();
+
+        vtu
+    }
+
+    fn to_pvd(&self) -> String {
+        let mut pvd = String::new();
+
+        writeln!(pvd, r#"<?xml version="1.0"?>"#).unwrap();
+        writeln!(pvd, r#"<VTKFile type="Collection" version="0.1">"#).unwrap();
+        writeln!(pvd, "<Collection>").unwrap();
+        self.state.frames.iter().for_each(|(time, file)| {
+            writeln!(pvd, r#"<DataSet timestep="{}" file="{}"/>"#, time, file).unwrap()
+        });
+        writeln!(pvd, "</Collection>").unwrap();
+        writeln!(pvd, "</VTKFile>").unwrap();
+
+        pvd
+    }
+}
@@ -0,0 +1,155 @@
+use crate::cfd::sph::simulation::SPH;
+use crate::scene::world_map::{Cell, WorldMap};
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunStatistics {
+    time: f32,
+    steps: usize,
+    door_open_time: f32,
+    vented: BTreeMap<(usize, usize), usize>,
+    removed: usize,
+    particles: ParticleCounts,
+}
+
+// Live particles after each step's removals.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ParticleCounts {
+    min: usize,
+    max: usize,
+    total: u64,
+    last: usize,
+}
+
+/// What a run did, as recorded in its manifest.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    simulated_time: f32,
+    steps: usize,
+    door_open_time: f32,
+    particles: ParticleSummary,
+    emitted: BTreeMap<String, usize>,
+    vented: Vec<VentSummary>,
+    removed_at_walls: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ParticleSummary {
+    min: usize,
+    max: usize,
+    mean: f64,
+    last: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct VentSummary {
+    tile: [usize; 2],
+    particles: usize,
+}
+
+impl RunStatistics {
+    pub fn new() -> Self {
+        Self {
+            time: 0.0,
+            steps: 0,
+            door_open_time: 0.0,
+            vented: BTreeMap::new(),
+            removed: 0,
+            particles: ParticleCounts::default(),
+        }
+    }
+
+    pub fn record(&mut self, world_map: &WorldMap, sph: &SPH, removed: &[Cell], dt: &Duration) {
+        self.time += dt.as_secs_f32();
+        self.steps += 1;
+
+        let count = sph.get_particles().len();
+        let particles = &mut self.particles;
+        particles.min = if self.steps == 1 {
+            count
+        } else {
+            particles.min.min(count)
+        };
+        particles.max = particles.max.max(count);
+        particles.total += count as u64;
+        particles.last = count;
+
+        if world_map.doors_open() {
+            self.door_open_time += dt.as_secs_f32();
+        }
+
+        for cell in removed {
+            match cell {
+                Cell::Vent(x, z) => *self.vented.entry((*x, *z)).or_insert(0) += 1,
+                _ => self.removed += 1,
+            }
+        }
+    }
+
+    pub fn summary(&self, world_map: &mut WorldMap) -> RunSummary {
+        let mut emitted = BTreeMap::new();
+
+        for actuator in world_map.get_actuators().iter() {
+            *emitted.entry(actuator.get_label().to_string()).or_insert(0) += actuator.get_emitted();
+        }
+
+        RunSummary {
+            simulated_time: self.time,
+            steps: self.steps,
+            door_open_time: self.door_open_time,
+            particles: ParticleSummary {
+                min: self.particles.min,
+                max: self.particles.max,
+                mean: self.particles.total as f64 / self.steps.max(1) as f64,
+                last: self.particles.last,
+            },
+            emitted,
+            vented: self
+                .vented
+                .iter()
+                .map(|((x, z), count)| VentSummary {
+                    tile: [*x, *z],
+                    particles: *count,
+                })
+                .collect(),
+            removed_at_walls: self.removed,
+        }
+    }
+
+    pub fn finish(&self, world_map: &mut WorldMap) {
+        println!("Simulated {:.2} s in {} steps", self.time, self.steps);
+        println!(
+            "{} to {} particles, {} at the end",
+            self.particles.min, self.particles.max, self.particles.last
+        );
+
+        for actuator in world_map.get_actuators().iter() {
+            if actuator.get_emitted() > 0 {
+                println!(
+                    "{}: {} particles emitted",
+                    actuator.get_label(),
+                    actuator.get_emitted()
+                );
+            }
+        }
+
+        for ((x, z), count) in self.vented.iter() {
+            println!(
+                "vent ({}, {}): {} particles out ({:.2}/s)",
+                x,
+                z,
+                count,
+                *count as f32 / self.time
+            );
+        }
+
+        if self.door_open_time > 0.0 {
+            println!("Doors open for {:.2} s", self.door_open_time);
+        }
+
+        println!("{} particles removed at walls", self.removed);
+    }
+}
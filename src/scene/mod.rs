@@ -1,11 +1,14 @@
 use crate::gfx::buffer::VertexBuffer;
-use crate::gfx::mesh::Mesh;
+use crate::gfx::mesh::{Mesh, Model};
 use crate::gfx::texture::Texture;
 use crate::gfx::vertex::InstanceVertex;
 use crate::scene::object::cube::Cube;
+use crate::scene::object::Transform;
 use crate::{Plane, Renderer};
 
 pub mod object;
+pub mod room;
+pub mod schedule;
 pub mod world_map;
 
 pub struct Scene {
@@ -14,6 +17,8 @@ pub struct Scene {
     floor_instance_buffer: VertexBuffer,
     wall_mesh: Mesh,
     wall_instance_buffer: VertexBuffer,
+    room_mesh: Option<Mesh>,
+    room_instance_buffer: VertexBuffer,
 }
 
 impl Scene {
@@ -23,6 +28,7 @@ impl Scene {
         user_position: (f32, f32),
         floor_instances: Vec<InstanceVertex>,
         wall_instances: Vec<InstanceVertex>,
+        room: Option<Model>,
     ) -> Self {
         let texture_layout = pipeline.get_bind_group_layout(2);
 
@@ -44,12 +50,21 @@ impl Scene {
         let wall_mesh = Cube::as_mesh(renderer, pipeline, wall_texture);
         let wall_instance_buffer = VertexBuffer::new(renderer, &wall_instances);
 
+        let room_mesh = room.map(|model| {
+            let texture = Texture::from_bytes(renderer, &texture_layout, 2, wall_texture_bytes);
+            Mesh::new(renderer, pipeline, model, texture)
+        });
+        let room_instance = InstanceVertex::from_transform(Transform::default());
+        let room_instance_buffer = VertexBuffer::new(renderer, &[room_instance]);
+
         Self {
             user_position,
             floor_mesh,
             floor_instance_buffer,
             wall_mesh,
             wall_instance_buffer,
+            room_mesh,
+            room_instance_buffer,
         }
     }
 
@@ -62,5 +77,9 @@ impl Scene {
             .draw_instanced(render_pass, &self.floor_instance_buffer);
         self.wall_mesh
             .draw_instanced(render_pass, &self.wall_instance_buffer);
+
+        if let Some(room_mesh) = &self.room_mesh {
+            room_mesh.draw_instanced(render_pass, &self.room_instance_buffer);
+        }
     }
 }
@@ -0,0 +1,99 @@
+use crate::gfx::mesh::Model;
+use crate::gfx::vertex::ModelVertex;
+use glam::{Vec2, Vec3};
+
+use std::collections::HashSet;
+
+/// Room geometry imported from an OBJ file, in meters with Y up.
+#[derive(Debug)]
+pub struct RoomMesh {
+    triangles: Vec<[Vec3; 3]>,
+}
+
+impl RoomMesh {
+    pub fn load(path: &str) -> Result<Self, tobj::LoadError> {
+        let (models, _) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+
+        let triangles = models
+            .iter()
+            .flat_map(|model| {
+                let mesh = &model.mesh;
+                let vertex = |i: u32| {
+                    let i = i as usize * 3;
+                    Vec3::new(
+                        mesh.positions[i],
+                        mesh.positions[i + 1],
+                        mesh.positions[i + 2],
+                    )
+                };
+
+                mesh.indices
+                    .chunks_exact(3)
+                    .map(move |face| [vertex(face[0]), vertex(face[1]), vertex(face[2])])
+            })
+            .collect();
+
+        Ok(Self { triangles })
+    }
+
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        self.triangles.iter().flatten().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), point| (min.min(*point), max.max(*point)),
+        )
+    }
+
+    /// Marks every voxel of side `cell_size` touched by a triangle, by sampling each triangle at
+    /// half the voxel spacing.
+    pub fn voxelize(&self, cell_size: f32) -> HashSet<[i64; 3]> {
+        let mut voxels = HashSet::new();
+
+        for [a, b, c] in self.triangles.iter() {
+            let longest = (*b - *a)
+                .length()
+                .max((*c - *b).length())
+                .max((*a - *c).length());
+            let steps = (2.0 * longest / cell_size).ceil().max(1.0) as usize;
+
+            for i in 0..=steps {
+                for j in 0..=(steps - i) {
+                    let (u, v) = (i as f32 / steps as f32, j as f32 / steps as f32);
+                    let point = *a + u * (*b - *a) + v * (*c - *a);
+                    let voxel = (point / cell_size).floor();
+
+                    voxels.insert([voxel.x as i64, voxel.y as i64, voxel.z as i64]);
+                }
+            }
+        }
+
+        voxels
+    }
+
+    pub fn to_model(&self) -> Model {
+        let vertices = self
+            .triangles
+            .iter()
+            .flat_map(|[a, b, c]| {
+                let normal = (*b - *a).cross(*c - *a).normalize_or_zero();
+                let n = normal.abs();
+
+                [*a, *b, *c].map(|point| {
+                    let tex_coords = if n.y >= n.x && n.y >= n.z {
+                        Vec2::new(point.x, point.z)
+                    } else if n.x >= n.z {
+                        Vec2::new(point.z, point.y)
+                    } else {
+                        Vec2::new(point.x, point.y)
+                    };
+
+                    ModelVertex::new(point, tex_coords, normal)
+                })
+            })
+            .collect();
+
+        Model {
+            vertices,
+            indices: None,
+        }
+    }
+}
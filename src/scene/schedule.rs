@@ -0,0 +1,291 @@
+use crate::cfd::config::{ScheduleConfig, ScheduleSegment};
+use crate::cfd::units;
+use polars::prelude::{CsvReadOptions, DataFrame, DataType, PolarsError, PolarsResult, SerReader};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduleState {
+    pub intensity: f32,
+    pub temperature: Option<f32>,
+    pub initial_velocity: Option<f32>,
+    pub interval: Option<f32>,
+}
+
+impl Default for ScheduleState {
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            temperature: None,
+            initial_velocity: None,
+            interval: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    time: f32,
+    intensity: Option<f32>,
+    temperature: Option<f32>,
+    initial_velocity: Option<f32>,
+    interval: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum Schedule {
+    Segments(Vec<ScheduleSegment>),
+    TimeSeries(Vec<Keyframe>),
+}
+
+impl Schedule {
+    pub fn new(config: &ScheduleConfig) -> Result<Self, String> {
+        match config {
+            ScheduleConfig::Segments(segments) => Ok(Schedule::Segments(segments.clone())),
+            ScheduleConfig::TimeSeries { csv } => Self::read_keyframes(csv)
+                .map(Schedule::TimeSeries)
+                .map_err(|e| format!("could not read schedule {}: {}", csv, e)),
+        }
+    }
+
+    pub fn at(&self, time: f32) -> ScheduleState {
+        match self {
+            Schedule::Segments(segments) => Self::segment_at(segments, time),
+            Schedule::TimeSeries(keyframes) => Self::keyframe_at(keyframes, time),
+        }
+    }
+
+    fn segment_at(segments: &[ScheduleSegment], time: f32) -> ScheduleState {
+        let segment = match segments
+            .iter()
+            .find(|segment| time >= segment.start && time < segment.end)
+        {
+            Some(segment) => segment,
+            None => {
+                return ScheduleState {
+                    intensity: 0.0,
+                    ..Default::default()
+                }
+            }
+        };
+
+        let intensity = match segment.ramp_to {
+            Some(ramp_to) => {
+                let t = (time - segment.start) / (segment.end - segment.start);
+                lerp(segment.intensity, ramp_to, t)
+            }
+            None => segment.intensity,
+        };
+
+        ScheduleState {
+            intensity,
+            temperature: segment.temperature,
+            initial_velocity: segment.initial_velocity,
+            interval: segment.interval,
+        }
+    }
+
+    fn keyframe_at(keyframes: &[Keyframe], time: f32) -> ScheduleState {
+        let next = keyframes.iter().position(|keyframe| keyframe.time > time);
+
+        let (a, b) = match next {
+            Some(0) => (keyframes[0], keyframes[0]),
+            Some(i) => (keyframes[i - 1], keyframes[i]),
+            None => match keyframes.last() {
+                Some(last) => (*last, *last),
+                None => return ScheduleState::default(),
+            },
+        };
+
+        let t = if b.time > a.time {
+            (time - a.time) / (b.time - a.time)
+        } else {
+            0.0
+        };
+
+        let interpolate = |a: Option<f32>, b: Option<f32>| match (a, b) {
+            (Some(a), Some(b)) => Some(lerp(a, b, t)),
+            (a, _) => a,
+        };
+
+        ScheduleState {
+            intensity: interpolate(a.intensity, b.intensity).unwrap_or(1.0),
+            temperature: interpolate(a.temperature, b.temperature),
+            initial_velocity: interpolate(a.initial_velocity, b.initial_velocity),
+            interval: interpolate(a.interval, b.interval),
+        }
+    }
+
+    fn read_keyframes(path: &str) -> PolarsResult<Vec<Keyframe>> {
+        let df = CsvReadOptions::default()
+            .with_has_header(true)
+            .try_into_reader_with_file_path(Some(path.into()))?
+            .finish()?;
+
+        let time = Self::read_column(&df, "time")?;
+        let intensity = Self::read_column(&df, "intensity")?;
+        let temperature = Self::read_column(&df, "temperature")?;
+        let initial_velocity = Self::read_column(&df, "initial_velocity")?;
+        let interval = Self::read_column(&df, "interval")?;
+
+        let mut keyframes = (0..df.height())
+            .filter_map(|i| {
+                time[i].map(|time| Keyframe {
+                    time,
+                    intensity: intensity[i],
+                    temperature: temperature[i],
+                    initial_velocity: initial_velocity[i],
+                    interval: interval[i],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(keyframes)
+    }
+
+    fn read_column(df: &DataFrame, name: &str) -> PolarsResult<Vec<Option<f32>>> {
+        if !df
+            .get_column_names()
+            .iter()
+            .any(|column| column.as_str() == name)
+        {
+            return Ok(vec![None; df.height()]);
+        }
+
+        let series = df.column(name)?.as_materialized_series();
+
+        // A column with units is read as text.
+        if series.dtype() == &DataType::String {
+            return series
+                .str()?
+                .into_iter()
+                .map(|cell| {
+                    cell.map(|text| units::convert_schedule_cell(name, text).map(|v| v as f32))
+                        .transpose()
+                })
+                .collect::<Result<_, _>>()
+                .map_err(|e| PolarsError::ComputeError(format!("{}: {}", name, e).into()));
+        }
+
+        let series = series.cast(&DataType::Float32)?;
+
+        Ok(series.f32()?.into_iter().collect())
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_file;
+
+    fn schedule(yaml: &str) -> Schedule {
+        Schedule::new(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    fn intensities(schedule: &Schedule, times: &[f32]) -> Vec<f32> {
+        times
+            .iter()
+            .map(|time| schedule.at(*time).intensity)
+            .collect()
+    }
+
+    #[test]
+    fn segments_start_inclusive_and_end_exclusive() {
+        let schedule = schedule(
+            "- {start: 0, end: 10}\n- {start: 10, end: 20, intensity: 0.5, temperature: 40}\n",
+        );
+
+        assert_eq!(
+            intensities(&schedule, &[0.0, 9.99, 10.0, 19.99, 20.0]),
+            [1.0, 1.0, 0.5, 0.5, 0.0]
+        );
+        assert_eq!(schedule.at(10.0).temperature, Some(40.0));
+        assert_eq!(schedule.at(9.99).temperature, None);
+    }
+
+    #[test]
+    fn segments_ramp_to_their_end() {
+        let schedule = schedule("- {start: 10, end: 20, intensity: 0.0, ramp_to: 1.0}\n");
+
+        assert_eq!(intensities(&schedule, &[10.0, 15.0]), [0.0, 0.5]);
+    }
+
+    #[test]
+    fn devices_are_off_after_the_last_segment() {
+        let schedule = schedule("- {start: 0, end: 5, temperature: 40}\n");
+        let state = schedule.at(60.0);
+
+        assert_eq!(state.intensity, 0.0);
+        assert_eq!(state.temperature, None);
+    }
+
+    #[test]
+    fn empty_schedules() {
+        let segments = schedule("[]");
+        let keyframes = Schedule::TimeSeries(Vec::new());
+
+        assert_eq!(segments.at(1.0).intensity, 0.0);
+        assert_eq!(keyframes.at(1.0).intensity, 1.0);
+        assert_eq!(keyframes.at(1.0).temperature, None);
+    }
+
+    #[test]
+    fn keyframes_interpolate_and_hold_the_last_value() {
+        let csv = temp_file(
+            "schedule",
+            "keyframes.csv",
+            "time,intensity,temperature\n10,0.0,20\n0,1.0,30\n20,1.0,\n",
+        );
+        let schedule = schedule(&format!("csv: {}", csv));
+
+        assert_eq!(
+            intensities(&schedule, &[0.0, 5.0, 15.0, 30.0]),
+            [1.0, 0.5, 0.5, 1.0]
+        );
+        assert_eq!(schedule.at(5.0).temperature, Some(25.0));
+        assert_eq!(schedule.at(30.0).temperature, None);
+    }
+
+    #[test]
+    fn keyframes_read_units() {
+        let csv = temp_file(
+            "schedule",
+            "units.csv",
+            "time,intensity,temperature,interval\n0,0.0,20,50 ms\n2 min,1.0,300 K,0.1\n",
+        );
+        let schedule = schedule(&format!("csv: {}", csv));
+        let state = schedule.at(120.0);
+
+        assert_eq!(state.intensity, 1.0);
+        assert!((state.temperature.unwrap() - 26.85).abs() < 1e-4);
+        assert_eq!(schedule.at(0.0).interval, Some(0.05));
+        assert_eq!(schedule.at(60.0).intensity, 0.5);
+    }
+
+    #[test]
+    fn keyframes_reject_units_of_another_dimension() {
+        let csv = temp_file("schedule", "wrong.csv", "time,intensity\n0,0.0\n2 m,1.0\n");
+        let config = serde_yaml::from_str(&format!("csv: {}", csv)).unwrap();
+        let error = Schedule::new(&config).unwrap_err();
+
+        assert!(
+            error.ends_with("time: '2 m' is a length, expected a time"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn missing_csv_is_an_error() {
+        let error = Schedule::new(&ScheduleConfig::TimeSeries {
+            csv: "missing-schedule.csv".to_string(),
+        })
+        .unwrap_err();
+
+        assert!(error.starts_with("could not read schedule missing-schedule.csv"));
+    }
+}
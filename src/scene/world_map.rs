@@ -1,138 +1,374 @@
-use crate::cfd::config::{ActuatorConfig, Config, FluidType, ParticleConfig, SensorConfig};
+use crate::cfd::config::{
+    ActuatorConfig, ActuatorKind, Config, DevicePlacement, EmitterConfig, FanConfig, HeaterConfig,
+    InletConfig, Nozzle, Quantity, SensorConfig, TableFormat, TileKind,
+};
+use crate::cfd::sph::simulation::SPH;
 use crate::gfx::vertex::InstanceVertex;
 use crate::scene::object::Transform;
+use crate::scene::room::RoomMesh;
+use crate::scene::schedule::{Schedule, ScheduleState};
 use crate::{Renderer, Scene, SimulationParticle};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use glam::{EulerRot, Quat, Vec3};
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
 
+use std::f32::consts::TAU;
+use std::fmt;
 use std::time::Duration;
 
-#[derive(Debug)]
-struct ActuatorParticle {
-    size: f32,
-    color: Vec3,
-}
-
-impl ActuatorParticle {
-    pub fn new(config: &ParticleConfig) -> Self {
-        Self {
-            size: config.size,
-            color: config.color,
-        }
-    }
-}
-
 #[derive(Debug)]
 pub struct Actuator {
-    rng: ThreadRng,
+    label: String,
+    rng: ChaCha8Rng,
     position: Vec3,
     direction: Vec3,
-    initial_velocity: f32,
-    temperature: Option<f32>,
-    range: Vec3,
-    fluid_type: FluidType,
-    interval: f32,
+    kind: ActuatorKind,
+    schedule: Option<Schedule>,
+    mass: f32,
+    time: f32,
     dt: f32,
-    particle: ActuatorParticle,
+    pending: f32,
+    emitted: usize,
 }
 
 impl Actuator {
-    pub fn new(x: f32, z: f32, config: &ActuatorConfig) -> Self {
+    pub fn new(
+        label: String,
+        position: Vec3,
+        direction: Vec3,
+        config: &ActuatorConfig,
+        mass: f32,
+        rng: ChaCha8Rng,
+    ) -> Self {
+        // Schedules are checked by validate; one whose file has gone since is dropped.
+        let schedule = config.schedule.as_ref().and_then(|schedule| {
+            Schedule::new(schedule)
+                .map_err(|e| eprintln!("Ignoring the schedule of {}: {}", label, e))
+                .ok()
+        });
+
         Self {
-            rng: rand::thread_rng(),
-            position: Vec3::new(x, config.height, z),
-            direction: config.direction,
-            initial_velocity: config.initial_velocity,
-            temperature: config.temperature,
-            range: config.range,
-            fluid_type: config.fluid_type,
-            interval: config.interval,
+            label,
+            rng,
+            position,
+            direction,
+            kind: config.kind,
+            schedule,
+            mass,
+            time: 0.0,
             dt: 0.0,
-            particle: ActuatorParticle::new(&config.particle),
+            pending: 0.0,
+            emitted: 0,
         }
     }
 
-    pub fn emit_particle(&mut self, dt: &Duration) -> Option<SimulationParticle> {
-        self.dt += dt.as_secs_f32();
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
 
-        if self.dt < self.interval {
-            return None;
+    pub fn get_emitted(&self) -> usize {
+        self.emitted
+    }
+
+    pub fn save_state(&self) -> ActuatorState {
+        ActuatorState {
+            label: self.label.clone(),
+            rng: self.rng.clone(),
+            time: self.time,
+            dt: self.dt,
+            pending: self.pending,
+            emitted: self.emitted,
+        }
+    }
+
+    pub fn restore_state(&mut self, state: ActuatorState) {
+        self.rng = state.rng;
+        self.time = state.time;
+        self.dt = state.dt;
+        self.pending = state.pending;
+        self.emitted = state.emitted;
+    }
+
+    pub fn actuate(&mut self, sph: &mut SPH, dt: &Duration) {
+        self.time += dt.as_secs_f32();
+
+        let state = match &self.schedule {
+            Some(schedule) => schedule.at(self.time),
+            None => ScheduleState::default(),
+        };
+
+        if state.intensity <= 0.0 {
+            return;
         }
 
-        self.dt = 0.0;
+        match self.kind {
+            ActuatorKind::Emitter(mut config) => {
+                config.temperature = state.temperature.or(config.temperature);
+                config.initial_velocity = state.initial_velocity.unwrap_or(config.initial_velocity);
+                config.interval = state
+                    .interval
+                    .or(config.interval)
+                    .map(|interval| interval / state.intensity);
+                config.flow_rate = config
+                    .flow_rate
+                    .map(|flow_rate| flow_rate * state.intensity);
 
-        let jitter_x: f32 = self.rng.gen::<f32>() * self.range.x;
-        let jitter_y: f32 = self.rng.gen::<f32>() * self.range.y;
-        let jitter_z: f32 = self.rng.gen::<f32>() * self.range.z;
+                for _ in 0..self.particles_to_emit(&config, dt) {
+                    let particle = self.emit_particle(&config);
+                    sph.add_particle(particle);
+                    self.emitted += 1;
+                }
+            }
+            ActuatorKind::Fan(mut config) => {
+                config.acceleration *= state.intensity;
+                self.blow(&config, sph);
+            }
+            ActuatorKind::Heater(mut config) => {
+                config.power *= state.intensity;
+                self.heat(&config, sph, dt);
+            }
+            ActuatorKind::Extractor(config) => {
+                let (position, radius) = (self.position, config.radius);
+                sph.remove_particles(|particle| particle.position.distance(position) <= radius);
+            }
+        }
+    }
+
+    fn particles_to_emit(&mut self, config: &EmitterConfig, dt: &Duration) -> usize {
+        match (config.flow_rate, config.interval) {
+            (Some(flow_rate), _) => {
+                self.pending += flow_rate * dt.as_secs_f32() / self.mass;
+                let count = self.pending.floor();
+                self.pending -= count;
+
+                count as usize
+            }
+            (None, Some(interval)) => {
+                self.dt += dt.as_secs_f32();
+
+                if self.dt < interval {
+                    return 0;
+                }
+
+                self.dt = 0.0;
+
+                1
+            }
+            (None, None) => 0,
+        }
+    }
 
-        let position = Vec3::new(
-            self.position.x + jitter_x,
-            self.position.y + jitter_y,
-            self.position.z + jitter_z,
+    fn emit_particle(&mut self, config: &EmitterConfig) -> SimulationParticle {
+        let axis = self.direction.normalize_or_zero();
+        let (side, up) = Self::basis(axis);
+
+        let jitter = Vec3::new(
+            (self.rng.gen::<f32>() - 0.5) * config.range.x,
+            (self.rng.gen::<f32>() - 0.5) * config.range.y,
+            (self.rng.gen::<f32>() - 0.5) * config.range.z,
         );
 
-        let velocity = self.direction * self.initial_velocity;
+        let (offset, direction) = match config.nozzle {
+            Nozzle::Point => (Vec3::ZERO, axis),
+            Nozzle::Disc { radius } => {
+                let r = radius * self.rng.gen::<f32>().sqrt();
+                let angle = self.rng.gen::<f32>() * TAU;
+
+                (r * (angle.cos() * side + angle.sin() * up), axis)
+            }
+            Nozzle::Rectangle { width, height } => {
+                let u = (self.rng.gen::<f32>() - 0.5) * width;
+                let v = (self.rng.gen::<f32>() - 0.5) * height;
+
+                (u * side + v * up, axis)
+            }
+            Nozzle::Cone { half_angle } => {
+                let cos_theta = 1.0 - self.rng.gen::<f32>() * (1.0 - half_angle.to_radians().cos());
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let angle = self.rng.gen::<f32>() * TAU;
 
-        let temperature = match self.temperature {
+                (
+                    Vec3::ZERO,
+                    cos_theta * axis + sin_theta * (angle.cos() * side + angle.sin() * up),
+                )
+            }
+        };
+
+        let position = self.position + jitter + offset;
+
+        let spread = 1.0 + (self.rng.gen::<f32>() * 2.0 - 1.0) * config.velocity_spread;
+        let velocity = direction * config.initial_velocity * spread;
+
+        let temperature = match config.temperature {
             None => 25.0,
             Some(temperature) => temperature,
         };
 
-        let particle = SimulationParticle::new(
+        SimulationParticle::new(
             position,
             velocity,
             temperature,
-            self.fluid_type.clone(),
-            self.particle.size,
-            self.particle.color,
-        );
+            config.fluid_type,
+            config.particle.size,
+            config.particle.color,
+        )
+    }
 
-        Some(particle)
+    fn basis(axis: Vec3) -> (Vec3, Vec3) {
+        let side = match axis.cross(Vec3::Y).try_normalize() {
+            Some(side) => side,
+            None => Vec3::X,
+        };
+
+        (side, side.cross(axis))
     }
+
+    fn blow(&self, config: &FanConfig, sph: &mut SPH) {
+        let axis = self.direction.normalize_or_zero();
+        let spread = config.spread.to_radians().tan();
+
+        for particle in sph.get_particles_mut().iter_mut() {
+            let offset = particle.position - self.position;
+            let along = offset.dot(axis);
+
+            if along < 0.0 || along > config.length {
+                continue;
+            }
+
+            let across = (offset - along * axis).length();
+
+            if across <= config.radius + along * spread {
+                particle.apply_acceleration(axis * config.acceleration);
+            }
+        }
+    }
+
+    fn heat(&self, config: &HeaterConfig, sph: &mut SPH, dt: &Duration) {
+        let heat = config.power * dt.as_secs_f32();
+
+        for particle in sph.get_particles_mut().iter_mut() {
+            if particle.position.distance(self.position) > config.radius {
+                continue;
+            }
+
+            let heated = particle.temperature + heat;
+
+            // The cap only stops heating: particles arriving hotter than it keep their
+            // temperature, and a negative power cools without limit.
+            particle.temperature = match config.max_temperature {
+                Some(max_temperature) if heat > 0.0 => {
+                    heated.min(max_temperature).max(particle.temperature)
+                }
+                _ => heated,
+            };
+        }
+    }
+}
+
+/// Run-time state of an actuator, kept in checkpoints. Its configuration comes from the config
+/// the run is resumed with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActuatorState {
+    label: String,
+    rng: ChaCha8Rng,
+    time: f32,
+    dt: f32,
+    pending: f32,
+    emitted: usize,
 }
 
 #[derive(Debug)]
 pub struct Sensor {
-    label: char,
+    label: String,
     position: Vec3,
-    range: Vec3,
     output: Option<String>,
+    format: TableFormat,
+    quantities: Vec<Quantity>,
+    interval: Option<f32>,
 }
 
 impl Sensor {
-    pub fn new(label: char, x: f32, z: f32, config: &SensorConfig) -> Self {
+    pub fn new(label: String, position: Vec3, config: &SensorConfig) -> Self {
         Self {
             label,
-            position: Vec3::new(x, config.height, z),
-            range: config.range,
+            position,
             output: config.output.clone(),
+            format: config.format,
+            quantities: config.quantities.clone(),
+            interval: config.interval,
         }
     }
 
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_output(&self) -> Option<&String> {
+        self.output.as_ref()
+    }
+
+    pub fn get_format(&self) -> TableFormat {
+        self.format
+    }
+
+    pub fn get_interval(&self) -> Option<f32> {
+        self.interval
+    }
+
+    pub fn columns(&self) -> Vec<String> {
+        self.quantities
+            .iter()
+            .flat_map(|quantity| match quantity {
+                Quantity::Temperature => vec!["temperature".to_string()],
+                Quantity::Velocity => vec![
+                    "velocity_x".to_string(),
+                    "velocity_y".to_string(),
+                    "velocity_z".to_string(),
+                ],
+                Quantity::Speed => vec!["speed".to_string()],
+                Quantity::Density => vec!["density".to_string()],
+            })
+            .collect()
+    }
+
+    pub fn measure(&self, sph: &SPH) -> Vec<Option<f32>> {
+        let sample = sph.interpolate(self.position);
+
+        self.quantities
+            .iter()
+            .flat_map(|quantity| match quantity {
+                Quantity::Temperature => vec![sample.map(|s| s.temperature)],
+                Quantity::Velocity => vec![
+                    sample.map(|s| s.velocity.x),
+                    sample.map(|s| s.velocity.y),
+                    sample.map(|s| s.velocity.z),
+                ],
+                Quantity::Speed => vec![sample.map(|s| s.velocity.length())],
+                Quantity::Density => vec![sample.map(|s| s.density)],
+            })
+            .collect()
+    }
+
     pub fn inspect_particle(&self, particle: &SimulationParticle) {
         println!(
-            "Sensor({}, {}, {}, {:?}) detected particle: {:?}",
-            self.label, self.position, self.range, self.output, particle
+            "Sensor({}, {}, {:?}) detected particle: {:?}",
+            self.label, self.position, self.output, particle
         );
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct DeviceConfig {
-    actuators: Vec<ActuatorConfig>,
-    sensors: Vec<SensorConfig>,
-}
-
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Tile {
     Empty,
     Wall,
     Floor,
     User,
+    Door,
+    Vent,
+    Inlet,
     Device(char),
 }
 
@@ -143,64 +379,357 @@ impl Tile {
             '#' => Ok(Tile::Wall),
             '.' => Ok(Tile::Floor),
             '@' => Ok(Tile::User),
-            _ => Ok(Tile::Device(c)),
+            '+' => Ok(Tile::Door),
+            '~' => Ok(Tile::Vent),
+            '=' => Ok(Tile::Inlet),
+            _ => Err(c),
+        }
+    }
+
+    pub fn from_kind(kind: TileKind, c: char) -> Tile {
+        match kind {
+            TileKind::Empty => Tile::Empty,
+            TileKind::Wall | TileKind::Obstacle => Tile::Wall,
+            TileKind::Floor => Tile::Floor,
+            TileKind::User => Tile::User,
+            TileKind::Door => Tile::Door,
+            TileKind::Vent => Tile::Vent,
+            TileKind::Inlet => Tile::Inlet,
+            TileKind::Device => Tile::Device(c),
         }
     }
 }
 
+#[derive(Debug)]
+pub enum TileError {
+    UnknownGlyph {
+        glyph: char,
+        line: usize,
+        column: usize,
+    },
+    RaggedRow {
+        line: usize,
+        length: usize,
+        expected: usize,
+    },
+}
+
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileError::UnknownGlyph {
+                glyph,
+                line,
+                column,
+            } => write!(
+                f,
+                "unknown glyph '{}' at line {}, column {}; add it to the legend or define a device with that label",
+                glyph, line, column
+            ),
+            TileError::RaggedRow {
+                line,
+                length,
+                expected,
+            } => write!(
+                f,
+                "line {} has {} columns, expected {} like the widest row",
+                line, length, expected
+            ),
+        }
+    }
+}
+
+pub const CEILING_HEIGHT: f32 = 3.0;
+
+const DOOR_HEIGHT: f32 = 2.1;
+const VENT_OPENING: (f32, f32) = (2.4, 2.8);
+const INLET_OPENING: (f32, f32) = (0.3, 0.7);
+
+/// What a point in the room is made of, as seen by the particle boundary check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell {
+    Air,
+    Solid,
+    Vent(usize, usize),
+}
+
+/// A height slice of the room; walls in its map are solid between `bottom` and `top`.
+#[derive(Debug)]
+struct Layer {
+    bottom: f32,
+    top: f32,
+    tiles: Vec<Vec<Tile>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorldState {
+    time: f32,
+    doors_open: bool,
+    actuators: Vec<ActuatorState>,
+}
+
 #[derive(Debug)]
 pub struct WorldMap {
     tiles: Vec<Vec<Tile>>,
-    actuators: HashMap<char, Actuator>,
-    sensors: HashMap<char, Sensor>,
+    layers: Vec<Layer>,
+    room: Option<RoomMesh>,
+    voxels: HashSet<[i64; 3]>,
+    bounds: (Vec3, Vec3),
+    heights: (f32, f32),
+    cell_size: f32,
+    placements: Vec<DevicePlacement>,
+    actuators: Vec<Actuator>,
+    sensors: Vec<Sensor>,
+    openings: HashMap<[usize; 2], (f32, f32)>,
+    door: Option<Schedule>,
+    doors_open: bool,
+    time: f32,
 }
 
 impl WorldMap {
     pub fn new(config: &Config) -> Self {
-        let tiles: Vec<Vec<Tile>> = config
-            .get_environment()
-            .lines()
-            .map(|line| {
-                line.chars()
-                    .map(|c| Tile::from(c).expect("Invalid character"))
-                    .collect()
+        let tiles = Self::parse_tiles(config, config.get_environment())
+            .unwrap_or_else(|errors| panic!("invalid map: {}", errors[0]));
+        let cell_size = config.get_cell_size();
+        let placements = Self::placements(config, &tiles);
+        let targets = Self::locate_devices(config, &placements);
+
+        let mut actuators = Vec::new();
+        let mut sensors = Vec::new();
+        let mut streams = HashMap::new();
+        let mut openings = HashMap::new();
+        let mass = config.get_simulation_config().mass;
+
+        for placement in placements.iter() {
+            if let Some(actuator) = config.get_actuator_by_label(&placement.label) {
+                let position = Self::device_position(
+                    placement,
+                    actuator.height,
+                    actuator.offset,
+                    actuator.position,
+                    cell_size,
+                );
+                let direction =
+                    Self::resolve_direction(&tiles, &targets, placement, position, actuator);
+                let reach = match &actuator.kind {
+                    ActuatorKind::Emitter(emitter) => emitter.range.y / 2.0,
+                    _ => 0.0,
+                };
+
+                Self::open_wall(
+                    &tiles,
+                    &mut openings,
+                    placement,
+                    position.y,
+                    reach.max(cell_size / 2.0),
+                );
+
+                actuators.push(Actuator::new(
+                    placement.label.clone(),
+                    position,
+                    direction,
+                    actuator,
+                    mass,
+                    Self::actuator_rng(config.get_seed(), &placement.label, &mut streams),
+                ));
+            }
+
+            if let Some(sensor) = config.get_sensor_by_label(&placement.label) {
+                let position = Self::device_position(
+                    placement,
+                    sensor.height,
+                    sensor.offset,
+                    sensor.position,
+                    cell_size,
+                );
+
+                Self::open_wall(
+                    &tiles,
+                    &mut openings,
+                    placement,
+                    position.y,
+                    cell_size / 2.0,
+                );
+                sensors.push(Sensor::new(placement.label.clone(), position, sensor));
+            }
+        }
+
+        // Wall-mounted glyphs without a device keep the wall closed.
+        Self::enumerate_tiles(&tiles)
+            .filter(|(x, z, tile)| {
+                matches!(tile, Tile::Device(_))
+                    && Self::is_wall_mounted(&tiles, *x as i64, *z as i64)
             })
-            .collect();
+            .for_each(|(x, z, _)| {
+                openings
+                    .entry([x as usize, z as usize])
+                    .or_insert((0.0, 0.0));
+            });
 
-        let mut actuators = HashMap::new();
-        let mut sensors = HashMap::new();
+        if let Some(inlet) = config.get_inlet() {
+            Self::enumerate_tiles(&tiles)
+                .filter(|(_, _, tile)| matches!(tile, Tile::Inlet))
+                .for_each(|(x, z, _)| {
+                    let label = format!("inlet ({}, {})", x, z);
+                    let rng = Self::actuator_rng(config.get_seed(), &label, &mut streams);
 
-        tiles
+                    actuators.push(Self::create_inlet(
+                        &tiles, inlet, label, x, z, cell_size, mass, rng,
+                    ));
+                });
+        }
+
+        let mut bottom = 0.0;
+        let layers = config
+            .get_layers()
             .iter()
-            .enumerate()
-            .flat_map(|(z, row)| {
-                row.iter()
-                    .enumerate()
-                    .map(move |(x, tile)| (x as f32, z as f32, tile))
+            .map(|layer| {
+                let top = bottom + layer.height;
+                let layer = Layer {
+                    bottom,
+                    top,
+                    tiles: Self::parse_tiles(config, &layer.map)
+                        .unwrap_or_else(|errors| panic!("invalid layer map: {}", errors[0])),
+                };
+                bottom = top;
+
+                layer
             })
-            .for_each(|(x, z, tile)| match tile {
-                Tile::Device(c) => {
-                    match config.get_actuator_by_label(c) {
-                        Some(config) => {
-                            actuators.insert(*c, Actuator::new(x + 0.5, z + 0.5, config));
-                        }
-                        None => {}
-                    }
-
-                    match config.get_sensor_by_label(c) {
-                        Some(config) => {
-                            sensors.insert(*c, Sensor::new(*c, x, z, config));
-                        }
-                        None => {}
-                    }
-                }
-                _ => {}
-            });
+            .collect();
+
+        let room = config.get_mesh().map(|path| match RoomMesh::load(path) {
+            Ok(room) => room,
+            Err(e) => panic!("could not load mesh {}: {}", path, e),
+        });
+
+        let (voxels, bounds) = match &room {
+            Some(room) => (room.voxelize(cell_size), room.bounds()),
+            None => (HashSet::new(), (Vec3::ZERO, Vec3::ZERO)),
+        };
+        let heights = Self::room_heights(room.as_ref());
 
         Self {
             tiles,
+            layers,
+            room,
+            voxels,
+            bounds,
+            heights,
+            cell_size,
+            placements,
             actuators,
             sensors,
+            openings,
+            door: config
+                .get_door()
+                .and_then(|door| door.schedule.as_ref())
+                .and_then(|schedule| {
+                    Schedule::new(schedule)
+                        .map_err(|e| eprintln!("Ignoring the door schedule: {}", e))
+                        .ok()
+                }),
+            doors_open: config.get_door().is_some_and(|door| door.open),
+            time: 0.0,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_inlet(
+        tiles: &[Vec<Tile>],
+        inlet: &InletConfig,
+        label: String,
+        x: f32,
+        z: f32,
+        cell_size: f32,
+        mass: f32,
+        rng: ChaCha8Rng,
+    ) -> Actuator {
+        let (bottom, top) = INLET_OPENING;
+        let emitter = EmitterConfig {
+            initial_velocity: inlet.velocity,
+            velocity_spread: 0.0,
+            temperature: Some(inlet.temperature),
+            range: Vec3::new(cell_size, top - bottom, cell_size),
+            nozzle: Nozzle::Point,
+            fluid_type: inlet.fluid_type,
+            interval: None,
+            flow_rate: Some(inlet.flow_rate),
+            particle: inlet.particle,
+        };
+
+        let direction = Self::facing_into_room(tiles, x as i64, z as i64);
+        let config = ActuatorConfig {
+            height: (bottom + top) / 2.0,
+            offset: Vec3::ZERO,
+            position: None,
+            direction: Some(direction),
+            facing: None,
+            aim_at: None,
+            kind: ActuatorKind::Emitter(emitter),
+            schedule: None,
+        };
+
+        let position = Vec3::new((x + 0.5) * cell_size, config.height, (z + 0.5) * cell_size);
+
+        Actuator::new(label, position, direction, &config, mass, rng)
+    }
+
+    // Each actuator draws from its own stream, keyed by its label and how many actuators
+    // before it share the label, so adding or moving other devices leaves its numbers alone.
+    fn actuator_rng(seed: u64, label: &str, streams: &mut HashMap<String, u64>) -> ChaCha8Rng {
+        let occurrence = streams.entry(label.to_string()).or_insert(0);
+        let hash = label.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(hash.wrapping_add(*occurrence));
+        *occurrence += 1;
+
+        rng
+    }
+
+    /// Advances the door schedule; doors are open while its intensity is positive.
+    pub fn update(&mut self, dt: &Duration) {
+        self.time += dt.as_secs_f32();
+
+        if let Some(door) = &self.door {
+            self.doors_open = door.at(self.time).intensity > 0.0;
+        }
+    }
+
+    pub fn doors_open(&self) -> bool {
+        self.doors_open
+    }
+
+    pub fn save_state(&self) -> WorldState {
+        WorldState {
+            time: self.time,
+            doors_open: self.doors_open,
+            actuators: self.actuators.iter().map(Actuator::save_state).collect(),
+        }
+    }
+
+    /// Restores a saved state. Actuators are matched by label, in map order among those sharing
+    /// one; saved actuators missing from this map are dropped.
+    pub fn restore_state(&mut self, state: WorldState) {
+        self.time = state.time;
+        self.doors_open = state.doors_open;
+
+        let mut seen = HashMap::new();
+
+        for saved in state.actuators {
+            let occurrence = seen.entry(saved.label.clone()).or_insert(0);
+            let actuator = self
+                .actuators
+                .iter_mut()
+                .filter(|actuator| actuator.label == saved.label)
+                .nth(*occurrence);
+            *occurrence += 1;
+
+            if let Some(actuator) = actuator {
+                actuator.restore_state(saved);
+            }
         }
     }
 
@@ -212,51 +741,387 @@ impl WorldMap {
         self.iter_tiles().for_each(|(x, z, tile)| match tile {
             Tile::Empty => {}
             Tile::Wall => {
-                let instance = Self::create_wall_instance(x, z);
+                let instance = self.create_wall_instance(x, z);
                 wall_instances.push(instance);
             }
             Tile::User => {
-                user_position = (x, z);
-                let instance = Self::create_floor_instance(x, z);
+                user_position = (x * self.cell_size, z * self.cell_size);
+                let instance = self.create_floor_instance(x, z);
                 floor_instances.push(instance);
             }
+            Tile::Device(_) if self.openings.contains_key(&[x as usize, z as usize]) => {
+                let (bottom, top) = self.openings[&[x as usize, z as usize]];
+
+                if bottom > 0.0 {
+                    wall_instances.push(self.create_block_instance(x, z, 0.0, bottom));
+                }
+
+                if top < CEILING_HEIGHT {
+                    let bottom = top.max(0.0);
+                    wall_instances.push(self.create_block_instance(
+                        x,
+                        z,
+                        bottom,
+                        CEILING_HEIGHT - bottom,
+                    ));
+                }
+            }
             Tile::Floor | Tile::Device(_) => {
-                let instance = Self::create_floor_instance(x, z);
+                let instance = self.create_floor_instance(x, z);
                 floor_instances.push(instance);
             }
+            Tile::Door => {
+                floor_instances.push(self.create_floor_instance(x, z));
+                wall_instances.push(self.create_block_instance(
+                    x,
+                    z,
+                    DOOR_HEIGHT,
+                    CEILING_HEIGHT - DOOR_HEIGHT,
+                ));
+            }
+            Tile::Vent | Tile::Inlet => {
+                let (bottom, top) = match tile {
+                    Tile::Vent => VENT_OPENING,
+                    _ => INLET_OPENING,
+                };
+
+                wall_instances.push(self.create_block_instance(x, z, 0.0, bottom));
+                wall_instances.push(self.create_block_instance(x, z, top, CEILING_HEIGHT - top));
+            }
         });
 
+        for layer in self.layers.iter() {
+            Self::enumerate_tiles(&layer.tiles)
+                .filter(|(_, _, tile)| matches!(tile, Tile::Wall))
+                .for_each(|(x, z, _)| {
+                    let instance =
+                        self.create_block_instance(x, z, layer.bottom, layer.top - layer.bottom);
+                    wall_instances.push(instance);
+                });
+        }
+
         Scene::new(
             renderer,
             pipeline,
             user_position,
             floor_instances,
             wall_instances,
+            self.room.as_ref().map(RoomMesh::to_model),
         )
     }
 
     fn iter_tiles(&self) -> impl Iterator<Item = (f32, f32, &Tile)> {
-        self.tiles.iter().enumerate().flat_map(|(z, row)| {
+        Self::enumerate_tiles(&self.tiles)
+    }
+
+    /// Reads a map with the built-in glyphs, overridden by the config `legend`. Any other glyph
+    /// must be the label of an actuator or sensor, and every row must be as wide as the first.
+    pub fn parse_tiles(config: &Config, map: &str) -> Result<Vec<Vec<Tile>>, Vec<TileError>> {
+        let mut errors = Vec::new();
+
+        // Blank lines after the last row and whitespace past the widest row are not part of
+        // the map, so rows only need padding up to the width of the room.
+        let lines = map.lines().collect::<Vec<_>>();
+        let rows = lines
+            .iter()
+            .rposition(|line| !line.trim().is_empty())
+            .map_or(0, |last| last + 1);
+        let width = lines[..rows]
+            .iter()
+            .map(|line| line.trim_end().chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let tiles = lines[..rows]
+            .iter()
+            .enumerate()
+            .map(|(z, line)| {
+                let length = line.chars().count();
+
+                if length < width {
+                    errors.push(TileError::RaggedRow {
+                        line: z + 1,
+                        length,
+                        expected: width,
+                    });
+                }
+
+                line.chars()
+                    .take(width)
+                    .enumerate()
+                    .map(|(x, c)| match config.get_legend().get(&c) {
+                        Some(kind) => Tile::from_kind(*kind, c),
+                        None => Tile::from(c).unwrap_or_else(|c| {
+                            let label = c.to_string();
+
+                            if config.get_actuator_by_label(&label).is_none()
+                                && config.get_sensor_by_label(&label).is_none()
+                            {
+                                errors.push(TileError::UnknownGlyph {
+                                    glyph: c,
+                                    line: z + 1,
+                                    column: x + 1,
+                                });
+                            }
+
+                            Tile::Device(c)
+                        }),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        match errors.is_empty() {
+            true => Ok(tiles),
+            false => Err(errors),
+        }
+    }
+
+    /// Glyph placements in row-major order, followed by the explicit `devices` list and
+    /// then by devices that only have an absolute `position`.
+    pub fn placements(config: &Config, tiles: &[Vec<Tile>]) -> Vec<DevicePlacement> {
+        let cell_size = config.get_cell_size();
+        let mut placements: Vec<DevicePlacement> = Self::enumerate_tiles(tiles)
+            .filter_map(|(x, z, tile)| match tile {
+                Tile::Device(c) => Some(DevicePlacement {
+                    label: c.to_string(),
+                    tile: [x as usize, z as usize],
+                    offset: Vec3::ZERO,
+                }),
+                _ => None,
+            })
+            .chain(config.get_devices().iter().cloned())
+            .collect();
+
+        let mut positioned =
+            config
+                .get_actuators()
+                .iter()
+                .filter_map(|(label, actuator)| actuator.position.map(|position| (label, position)))
+                .chain(config.get_sensors().iter().filter_map(|(label, sensor)| {
+                    sensor.position.map(|position| (label, position))
+                }))
+                .collect::<Vec<_>>();
+        positioned.sort_by_key(|(label, _)| label.as_str());
+
+        for (label, position) in positioned {
+            if placements.iter().any(|placement| &placement.label == label) {
+                continue;
+            }
+
+            placements.push(DevicePlacement {
+                label: label.clone(),
+                tile: [
+                    (position.x / cell_size).max(0.0) as usize,
+                    (position.z / cell_size).max(0.0) as usize,
+                ],
+                offset: Vec3::ZERO,
+            });
+        }
+
+        placements
+    }
+
+    /// Devices are anchored at the center of their tile, at their configured height. An
+    /// absolute `position` replaces the anchor and both offsets.
+    pub fn device_position(
+        placement: &DevicePlacement,
+        height: f32,
+        offset: Vec3,
+        position: Option<Vec3>,
+        cell_size: f32,
+    ) -> Vec3 {
+        if let Some(position) = position {
+            return position;
+        }
+
+        let [x, z] = placement.tile;
+        let (x, z) = ((x as f32 + 0.5) * cell_size, (z as f32 + 0.5) * cell_size);
+
+        Vec3::new(x, height, z) + offset + placement.offset
+    }
+
+    /// Position each label can be aimed at; the first placement of a label wins.
+    pub fn locate_devices(
+        config: &Config,
+        placements: &[DevicePlacement],
+    ) -> HashMap<String, Vec3> {
+        let mut targets = HashMap::new();
+
+        for placement in placements {
+            let position = match config.get_actuator_by_label(&placement.label) {
+                Some(actuator) => Self::device_position(
+                    placement,
+                    actuator.height,
+                    actuator.offset,
+                    actuator.position,
+                    config.get_cell_size(),
+                ),
+                None => match config.get_sensor_by_label(&placement.label) {
+                    Some(sensor) => Self::device_position(
+                        placement,
+                        sensor.height,
+                        sensor.offset,
+                        sensor.position,
+                        config.get_cell_size(),
+                    ),
+                    None => continue,
+                },
+            };
+
+            targets.entry(placement.label.clone()).or_insert(position);
+        }
+
+        targets
+    }
+
+    pub fn enumerate_tiles(tiles: &[Vec<Tile>]) -> impl Iterator<Item = (f32, f32, &Tile)> {
+        tiles.iter().enumerate().flat_map(|(z, row)| {
             row.iter()
                 .enumerate()
                 .map(move |(x, tile)| (x as f32, z as f32, tile))
         })
     }
 
-    pub fn get_actuators(&mut self) -> &mut HashMap<char, Actuator> {
+    pub fn resolve_direction(
+        tiles: &[Vec<Tile>],
+        targets: &HashMap<String, Vec3>,
+        placement: &DevicePlacement,
+        position: Vec3,
+        actuator: &ActuatorConfig,
+    ) -> Vec3 {
+        if let Some(direction) = actuator.direction {
+            return direction;
+        }
+
+        if let Some(facing) = actuator.facing {
+            return facing.to_direction();
+        }
+
+        if let Some(target) = actuator
+            .aim_at
+            .as_ref()
+            .and_then(|label| targets.get(label))
+        {
+            return (*target - position).normalize_or_zero();
+        }
+
+        let [x, z] = placement.tile;
+        let (x, z) = (x as i64, z as i64);
+
+        if Self::is_wall_mounted(tiles, x, z) {
+            return Self::facing_into_room(tiles, x, z);
+        }
+
+        Vec3::ZERO
+    }
+
+    /// Tile at a map column and row, or `None` outside the map.
+    pub fn get_tile(tiles: &[Vec<Tile>], x: i64, z: i64) -> Option<&Tile> {
+        if x < 0 || z < 0 {
+            return None;
+        }
+
+        tiles.get(z as usize).and_then(|row| row.get(x as usize))
+    }
+
+    /// A device glyph replaces a wall when it borders the outside of the room; one on a floor
+    /// tile between walls, as in a corridor or doorway, stands on the floor.
+    pub fn is_wall_mounted(tiles: &[Vec<Tile>], x: i64, z: i64) -> bool {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .any(|(dx, dz)| {
+                matches!(
+                    Self::get_tile(tiles, x + dx, z + dz),
+                    None | Some(Tile::Empty)
+                )
+            })
+    }
+
+    // A device mounted in a wall opens it in a band around its height, `reach` above and below;
+    // the rest of the tile stays solid.
+    fn open_wall(
+        tiles: &[Vec<Tile>],
+        openings: &mut HashMap<[usize; 2], (f32, f32)>,
+        placement: &DevicePlacement,
+        height: f32,
+        reach: f32,
+    ) {
+        let [x, z] = placement.tile;
+
+        if !matches!(
+            Self::get_tile(tiles, x as i64, z as i64),
+            Some(Tile::Device(_))
+        ) || !Self::is_wall_mounted(tiles, x as i64, z as i64)
+        {
+            return;
+        }
+
+        let (bottom, top) = (height - reach, height + reach);
+        let opening = openings.entry(placement.tile).or_insert((bottom, top));
+        *opening = (opening.0.min(bottom), opening.1.max(top));
+    }
+
+    fn facing_into_room(tiles: &[Vec<Tile>], x: i64, z: i64) -> Vec3 {
+        [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z]
+            .into_iter()
+            .filter(|direction| {
+                let tile = Self::get_tile(tiles, x + direction.x as i64, z + direction.z as i64);
+                matches!(tile, Some(Tile::Floor | Tile::User | Tile::Device(_)))
+            })
+            .fold(Vec3::ZERO, |sum, direction| sum + direction)
+            .normalize_or_zero()
+    }
+
+    pub fn get_actuators(&mut self) -> &mut Vec<Actuator> {
         &mut self.actuators
     }
 
-    pub fn get_sensor_by_label(&self, label: &char) -> Option<&Sensor> {
-        self.sensors.get(label)
+    pub fn get_sensor_by_label(&self, label: &str) -> Option<&Sensor> {
+        self.sensors.iter().find(|sensor| sensor.label == label)
     }
 
-    pub fn get_tile_in_position(&self, position: Vec3) -> &Tile {
-        let (x, z) = ((position.x) as usize, (position.z) as usize);
+    pub fn get_sensors(&self) -> &Vec<Sensor> {
+        &self.sensors
+    }
+
+    /// Floor and ceiling of the room: the mesh's lowest and highest points when there is one,
+    /// the fixed ceiling of tile maps otherwise.
+    pub fn room_heights(room: Option<&RoomMesh>) -> (f32, f32) {
+        match room {
+            Some(room) => {
+                let (min, max) = room.bounds();
+                (min.y, max.y)
+            }
+            None => (0.0, CEILING_HEIGHT),
+        }
+    }
+
+    /// Corners of the box the room fits in: the map between the floor and the ceiling, or the
+    /// mesh bounds when there is no map.
+    pub fn extent(&self) -> (Vec3, Vec3) {
+        if self.tiles.is_empty() {
+            return self.bounds;
+        }
 
-        if z <= self.tiles.len() && x <= self.tiles[z].len() {
-            let tile = &self.tiles[z][x];
+        let width = self.tiles.iter().map(Vec::len).max().unwrap_or(0);
+        let depth = self.tiles.len();
+        let (floor, ceiling) = self.heights;
 
+        (
+            Vec3::new(0.0, floor, 0.0),
+            Vec3::new(
+                width as f32 * self.cell_size,
+                ceiling,
+                depth as f32 * self.cell_size,
+            ),
+        )
+    }
+
+    pub fn get_tile_in_position(&self, position: Vec3) -> &Tile {
+        let (x, z) = self.tile_at(position);
+
+        if let Some(tile) = self.tiles.get(z).and_then(|row| row.get(x)) {
             return match tile {
                 Tile::User => &Tile::Floor,
                 Tile::Device(_) => &Tile::Floor,
@@ -267,40 +1132,220 @@ impl WorldMap {
         return &Tile::Empty;
     }
 
-    pub fn get_device_in_position(&self, position: Vec3) -> Option<char> {
-        let (x, z) = ((position.x) as usize, (position.z) as usize);
+    /// Classifies a point: walls, closed doors, the outside, and solid layer or mesh voxels are
+    /// solid; vent openings drain the room. Without a map, the room is the mesh bounding box.
+    pub fn cell_at(&self, position: Vec3) -> Cell {
+        let y = position.y;
+        let (floor, ceiling) = self.heights;
 
-        if z <= self.tiles.len() {
-            if x <= self.tiles[z].len() {
-                let tile = &self.tiles[z][x];
+        if y < floor || y > ceiling {
+            return Cell::Solid;
+        }
 
-                return match tile {
-                    Tile::Device(c) => Some(*c),
-                    _ => None,
-                };
+        let (x, z) = self.tile_at(position);
+        let within = |(bottom, top): (f32, f32)| y >= bottom && y < top;
+
+        if self.tiles.is_empty() {
+            let (min, max) = self.bounds;
+
+            if position.cmplt(min).any() || position.cmpgt(max).any() {
+                return Cell::Solid;
+            }
+        } else {
+            if let Some(opening) = self.openings.get(&[x, z]) {
+                if !within(*opening) {
+                    return Cell::Solid;
+                }
             }
+
+            match self.get_tile_in_position(position) {
+                Tile::Floor => {}
+                Tile::Door if self.doors_open && y < DOOR_HEIGHT => {}
+                Tile::Inlet if within(INLET_OPENING) => {}
+                Tile::Vent if within(VENT_OPENING) => return Cell::Vent(x, z),
+                _ => return Cell::Solid,
+            }
+        }
+
+        let voxel = (position / self.cell_size).floor();
+
+        if self
+            .voxels
+            .contains(&[voxel.x as i64, voxel.y as i64, voxel.z as i64])
+        {
+            return Cell::Solid;
         }
 
-        return None;
+        let blocked = self
+            .layers
+            .iter()
+            .filter(|layer| within((layer.bottom, layer.top)))
+            .any(|layer| {
+                matches!(
+                    Self::get_tile(&layer.tiles, x as i64, z as i64),
+                    Some(Tile::Wall)
+                )
+            });
+
+        match blocked {
+            true => Cell::Solid,
+            false => Cell::Air,
+        }
     }
 
-    fn create_floor_instance(x: f32, z: f32) -> InstanceVertex {
+    pub fn is_solid(&self, position: Vec3) -> bool {
+        self.cell_at(position) != Cell::Air
+    }
+
+    pub fn get_devices_in_position(&self, position: Vec3) -> Vec<&str> {
+        let (x, z) = self.tile_at(position);
+
+        self.placements
+            .iter()
+            .filter(|placement| placement.tile == [x, z])
+            .map(|placement| placement.label.as_str())
+            .collect()
+    }
+
+    fn tile_at(&self, position: Vec3) -> (usize, usize) {
+        (
+            (position.x / self.cell_size) as usize,
+            (position.z / self.cell_size) as usize,
+        )
+    }
+
+    fn create_floor_instance(&self, x: f32, z: f32) -> InstanceVertex {
         let transform = Transform::new(
-            Vec3::ONE,
+            Vec3::splat(self.cell_size),
             Quat::from_euler(EulerRot::XYZ, -90.0f32.to_radians(), 0.0, 0.0),
-            Vec3::new(x as f32, 0.0, z as f32 + 1.0),
+            Vec3::new(x, 0.0, z + 1.0) * self.cell_size,
         );
 
         InstanceVertex::from_transform(transform)
     }
 
-    fn create_wall_instance(x: f32, z: f32) -> InstanceVertex {
+    fn create_wall_instance(&self, x: f32, z: f32) -> InstanceVertex {
+        self.create_block_instance(x, z, 0.0, CEILING_HEIGHT)
+    }
+
+    fn create_block_instance(&self, x: f32, z: f32, bottom: f32, height: f32) -> InstanceVertex {
         let transform = Transform::new(
-            Vec3::new(1.0, 3.0, 1.0),
+            Vec3::new(self.cell_size, height, self.cell_size),
             Quat::IDENTITY,
-            Vec3::new(x as f32, 0.0, z as f32),
+            Vec3::new(x * self.cell_size, bottom, z * self.cell_size),
         );
 
         InstanceVertex::from_transform(transform)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfd::overrides::Overrides;
+    use crate::testing::temp_file;
+
+    // A closed box from (0, -1, 0) to (4, 4, 4): taller than the tile map ceiling and with its
+    // floor below zero.
+    const BOX: &str = "\
+v 0 -1 0
+v 4 -1 0
+v 4 4 0
+v 0 4 0
+v 0 -1 4
+v 4 -1 4
+v 4 4 4
+v 0 4 4
+f 1 2 3
+f 1 3 4
+f 5 7 6
+f 5 8 7
+f 1 5 6
+f 1 6 2
+f 4 3 7
+f 4 7 8
+f 1 4 8
+f 1 8 5
+f 2 6 7
+f 2 7 3
+";
+
+    fn mesh_room() -> WorldMap {
+        temp_file("mesh-room", "room.obj", BOX);
+        let filename = temp_file(
+            "mesh-room",
+            "room.yml",
+            "environment:\n  mesh: room.obj\n  cell_size: 0.5\nactuators: {}\nsensors: {}\n",
+        );
+        let config = Config::load(&filename, &Overrides::new()).unwrap();
+
+        WorldMap::new(&config)
+    }
+
+    #[test]
+    fn mesh_room_spans_the_mesh_heights() {
+        let world_map = mesh_room();
+
+        assert_eq!(world_map.cell_at(Vec3::new(2.0, 2.0, 2.0)), Cell::Air);
+        assert_eq!(world_map.cell_at(Vec3::new(2.0, 3.5, 2.0)), Cell::Air);
+        assert_eq!(world_map.cell_at(Vec3::new(2.0, -0.5, 2.0)), Cell::Air);
+    }
+
+    #[test]
+    fn mesh_room_is_solid_outside_the_mesh() {
+        let world_map = mesh_room();
+
+        assert_eq!(world_map.cell_at(Vec3::new(2.0, 4.5, 2.0)), Cell::Solid);
+        assert_eq!(world_map.cell_at(Vec3::new(2.0, -1.5, 2.0)), Cell::Solid);
+        assert_eq!(world_map.cell_at(Vec3::new(5.0, 2.0, 2.0)), Cell::Solid);
+        assert_eq!(world_map.cell_at(Vec3::new(2.0, 2.0, 0.1)), Cell::Solid);
+    }
+
+    fn parse(map: &str) -> Result<Vec<Vec<Tile>>, Vec<String>> {
+        let filename = temp_file(
+            "parse-tiles",
+            "devices.yml",
+            "environment: \"#\"\nactuators: {}\nsensors:\n  V: {height: 1.0}\n",
+        );
+        let config = Config::load(&filename, &Overrides::new()).unwrap();
+
+        WorldMap::parse_tiles(&config, map)
+            .map_err(|errors| errors.iter().map(ToString::to_string).collect())
+    }
+
+    fn size(tiles: &[Vec<Tile>]) -> Vec<usize> {
+        tiles.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn trailing_blank_lines_are_not_rows() {
+        let tiles = parse("###\n#V#\n###\n\n   \n").unwrap();
+
+        assert_eq!(size(&tiles), [3, 3, 3]);
+        assert_eq!(tiles[1][1], Tile::Device('V'));
+    }
+
+    #[test]
+    fn trailing_spaces_past_the_widest_row_are_ignored() {
+        let tiles = parse("###    \n#.#\n###  ").unwrap();
+
+        assert_eq!(size(&tiles), [3, 3, 3]);
+        assert_eq!(tiles[0][2], Tile::Wall);
+    }
+
+    #[test]
+    fn short_rows_are_errors() {
+        assert_eq!(
+            parse("####\n#.#\n####").unwrap_err(),
+            ["line 2 has 3 columns, expected 4 like the widest row"]
+        );
+    }
+
+    #[test]
+    fn unknown_glyphs_report_their_line_and_column() {
+        assert_eq!(
+            parse("####\n#.?#\n####").unwrap_err(),
+            ["unknown glyph '?' at line 2, column 3; add it to the legend or define a device with that label"]
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use std::path::PathBuf;
+
+/// Writes `contents` to `name` in a directory of its own under the system temp directory, so
+/// tests can go through the same readers as a run, and returns the file's path.
+pub fn temp_file(directory: &str, name: &str, contents: &str) -> String {
+    let directory =
+        std::env::temp_dir().join(format!("fluid-sense-{}-{}", std::process::id(), directory));
+    std::fs::create_dir_all(&directory).expect("could not create the test directory");
+
+    let path: PathBuf = directory.join(name);
+    std::fs::write(&path, contents).expect("could not write the test file");
+
+    path.to_string_lossy().to_string()
+}